@@ -0,0 +1,99 @@
+//! A minimal frontend that runs a program in an SDL window through `ocho::frontend`, without
+//! any of the options of the `ocho` binary: `cargo run --example sdl -- program.ch8`
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use ocho::{
+    chip8::{Chip8, Quirks, DISPLAY_HEIGHT, DISPLAY_WIDTH, PROGRAM_START, TIMER_FREQUENCY},
+    frontend::{
+        run_frame,
+        sdl::{KeyboardInput, TextureDisplay},
+    },
+};
+use sdl2::{
+    audio::{AudioCallback, AudioSpecDesired},
+    pixels::PixelFormatEnum,
+};
+
+/// Instructions executed per frame
+const IPF: u16 = 10;
+/// Size of a pixel of the display, in pixels of the window
+const SCALE: u32 = 10;
+/// Pitch of the buzzer, in Hz
+const PITCH: f32 = 440.0;
+/// Color of set pixels, as an RGBA value
+const FG: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+/// Color of unset pixels, as an RGBA value
+const BG: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+
+/// Square wave played by the buzzer
+struct SquareWave {
+    phase: f32,
+    phase_step: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            *x = if self.phase < 0.5 { 0.25 } else { -0.25 };
+            self.phase = (self.phase + self.phase_step) % 1.0;
+        }
+    }
+}
+
+fn main() -> Result<(), String> {
+    let path = std::env::args().nth(1).ok_or("usage: sdl PROGRAM")?;
+    let rom = std::fs::read(&path).map_err(|e| format!("\'{}\': {}", path, e))?;
+    let mut chip = Chip8::new(&rom, Quirks::default(), PROGRAM_START)?;
+
+    const WIDTH: u32 = DISPLAY_WIDTH as u32;
+    const HEIGHT: u32 = DISPLAY_HEIGHT as u32;
+    let sdl_context = sdl2::init()?;
+    let window = sdl_context
+        .video()?
+        .window("ocho", WIDTH * SCALE, HEIGHT * SCALE)
+        .position_centered()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    canvas
+        .set_logical_size(WIDTH, HEIGHT)
+        .map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH, HEIGHT)
+        .map_err(|e| e.to_string())?;
+
+    let desired_audio_spec = AudioSpecDesired {
+        freq: Some(44100),
+        channels: Some(1),
+        samples: None,
+    };
+    let mut audio = sdl_context
+        .audio()?
+        .open_playback(None, &desired_audio_spec, |spec| SquareWave {
+            phase: 0.0,
+            phase_step: PITCH / spec.freq as f32,
+        })?;
+    let mut input = KeyboardInput::new(sdl_context.event_pump()?);
+
+    // The timers tick once per frame
+    let frame_period = Duration::from_secs(1) / TIMER_FREQUENCY;
+    loop {
+        let start = Instant::now();
+        let mut display = TextureDisplay::new(&mut texture, FG, BG);
+        if !run_frame(&mut chip, IPF, &mut display, &mut input, &mut audio)? {
+            return Ok(());
+        }
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        if let Some(rest) = frame_period.checked_sub(start.elapsed()) {
+            thread::sleep(rest);
+        }
+    }
+}
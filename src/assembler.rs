@@ -0,0 +1,248 @@
+use std::collections::HashMap;
+
+use crate::instruction::Instruction::*;
+use crate::{chip8::PROGRAM_START, instruction::Instruction};
+
+/// Largest addressable memory location
+const MAX_ADDRESS: usize = 0xFFF;
+
+/// Assemble source code into a binary CHIP-8 program.
+///
+/// Each line holds at most one instruction, written using the mnemonics displayed by
+/// the disassembler (e.g., `LDB V0, 0x0A`).  Numbers may be written in hexadecimal
+/// (with a `0x` prefix) or decimal.  Comments start with `;` and run to the end of the
+/// line.  A line may start with a label (e.g., `loop:`), which can be used in place
+/// of any address operand.  `DB` writes a single byte of data (e.g., `DB 0xF0`).  Listings
+/// written by the disassembler can be assembled as-is, since the address and raw opcode
+/// columns at the start of a line (e.g., `0x0200: 0xA22A`) are skipped.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    // First pass: assign an address to each label
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut addr = PROGRAM_START;
+    for (line_number, line) in source.lines().enumerate().map(|(i, l)| (i + 1, l)) {
+        let mut line = skip_listing_columns(line.split(';').next().unwrap_or_default().trim());
+        if let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Result::Err(format!("line {}: invalid label '{}'", line_number, label));
+            }
+            if labels.insert(label, addr).is_some() {
+                return Result::Err(format!(
+                    "line {}: label '{}' is already defined",
+                    line_number, label
+                ));
+            }
+            line = rest.trim();
+        }
+        if !line.is_empty() {
+            lines.push((line_number, line));
+            addr += if data_operand(line).is_some() { 1 } else { 2 };
+        }
+    }
+
+    // Second pass: encode each instruction or byte of data
+    let mut rom = Vec::with_capacity(2 * lines.len());
+    for (line_number, line) in lines {
+        let error = |e| format!("line {}: {}", line_number, e);
+        if let Some(operand) = data_operand(line) {
+            rom.push(byte(operand).map_err(error)?);
+            continue;
+        }
+        let instr = parse_instruction(line, &labels).map_err(error)?;
+        let opcode = match instr {
+            // Unrecognized instructions are written as-is
+            Instruction::Err(instr) => instr,
+            _ => instr.encode().expect("Only `Err` cannot be encoded"),
+        };
+        rom.extend_from_slice(&opcode.to_be_bytes());
+    }
+
+    Ok(rom)
+}
+
+fn parse_instruction(line: &str, labels: &HashMap<&str, usize>) -> Result<Instruction, String> {
+    let (mnemonic, operands) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let operands: Vec<&str> = if operands.trim().is_empty() {
+        Vec::new()
+    } else {
+        operands.split(',').map(str::trim).collect()
+    };
+    let addr = |s: &str| address(s, labels);
+
+    let instr = match (mnemonic.to_uppercase().as_str(), operands.as_slice()) {
+        ("SYS", [nnn]) => Sys(addr(nnn)?),
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("JMP", [nnn]) => Jmp(addr(nnn)?),
+        ("CALL", [nnn]) => Call(addr(nnn)?),
+        ("SKEB", [x, nn]) => Skeb(register(x)?, byte(nn)?),
+        ("SKNEB", [x, nn]) => Skneb(register(x)?, byte(nn)?),
+        ("SKE", [x, y]) => Ske(register(x)?, register(y)?),
+        ("LDB", [x, nn]) => Ldb(register(x)?, byte(nn)?),
+        ("ADDB", [x, nn]) => Addb(register(x)?, byte(nn)?),
+        ("LD", [x, y]) => Ld(register(x)?, register(y)?),
+        ("OR", [x, y]) => Or(register(x)?, register(y)?),
+        ("AND", [x, y]) => And(register(x)?, register(y)?),
+        ("XOR", [x, y]) => Xor(register(x)?, register(y)?),
+        ("ADD", [x, y]) => Add(register(x)?, register(y)?),
+        ("SUB", [x, y]) => Sub(register(x)?, register(y)?),
+        ("SHR", [x, y]) => Shr(register(x)?, register(y)?),
+        ("SUBR", [x, y]) => Subr(register(x)?, register(y)?),
+        ("SHL", [x, y]) => Shl(register(x)?, register(y)?),
+        ("SKNE", [x, y]) => Skne(register(x)?, register(y)?),
+        ("LDI", [nnn]) => Ldi(addr(nnn)?),
+        ("JMPZ", [nnn]) => Jmpz(addr(nnn)?),
+        ("RND", [x, nn]) => Rnd(register(x)?, byte(nn)?),
+        ("DRAW", [x, y, n]) => Draw(register(x)?, register(y)?, nibble(n)?),
+        ("SKP", [x]) => Skp(register(x)?),
+        ("SKNP", [x]) => Sknp(register(x)?),
+        ("LDFT", [x]) => Ldft(register(x)?),
+        ("LDK", [x]) => Ldk(register(x)?),
+        ("LDDT", [x]) => Lddt(register(x)?),
+        ("LDST", [x]) => Ldst(register(x)?),
+        ("ADDI", [x]) => Addi(register(x)?),
+        ("FONT", [x]) => Font(register(x)?),
+        ("BCD", [x]) => Bcd(register(x)?),
+        ("SREG", [x]) => Sreg(register(x)?),
+        ("LREG", [x]) => Lreg(register(x)?),
+        ("ERR", [instr]) => Instruction::Err(number(instr, u16::MAX.into())? as u16),
+        _ => return Result::Err(format!("invalid instruction '{}'", line)),
+    };
+
+    Ok(instr)
+}
+
+/// Skip the address and the raw opcode or byte at the start of a line of disassembly
+/// (e.g., `0x0200: 0xA22A  LDI   0x22A`), leaving only the instruction
+fn skip_listing_columns(line: &str) -> &str {
+    let Some((addr, rest)) = line.split_once(':') else {
+        return line;
+    };
+    // Labels can't start with a digit, so this isn't a label
+    if number(addr.trim(), usize::MAX).is_err() {
+        return line;
+    }
+    let rest = rest.trim_start();
+    match rest.split_once(char::is_whitespace) {
+        Some((raw, instr)) if number(raw, u16::MAX.into()).is_ok() => instr.trim_start(),
+        _ => rest,
+    }
+}
+
+/// Operand of a `DB` line, which holds a byte of data instead of an instruction
+fn data_operand(line: &str) -> Option<&str> {
+    let (mnemonic, operand) = line.split_once(char::is_whitespace)?;
+    mnemonic.eq_ignore_ascii_case("DB").then(|| operand.trim())
+}
+
+/// Labels start with a letter or underscore, followed by letters, digits, or underscores
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Parse a number in hexadecimal (with a `0x` prefix) or decimal, no larger than `max`
+fn number(s: &str, max: usize) -> Result<usize, String> {
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|_| format!("'{}' is not a valid number", s))?;
+
+    if value > max {
+        return Result::Err(format!("{} is larger than the maximum of {:#X}", s, max));
+    }
+    Ok(value)
+}
+
+/// Parse a 12-bit address or label
+fn address(s: &str, labels: &HashMap<&str, usize>) -> Result<usize, String> {
+    match labels.get(s) {
+        Some(&addr) if addr > MAX_ADDRESS => {
+            Result::Err(format!("label '{}' is outside of addressable memory", s))
+        }
+        Some(&addr) => Ok(addr),
+        None if is_label(s) => Result::Err(format!("label '{}' is not defined", s)),
+        None => number(s, MAX_ADDRESS),
+    }
+}
+
+/// Parse an 8-bit value
+fn byte(s: &str) -> Result<u8, String> {
+    Ok(number(s, u8::MAX.into())? as u8)
+}
+
+/// Parse a 4-bit value
+fn nibble(s: &str) -> Result<u8, String> {
+    Ok(number(s, 0xF)? as u8)
+}
+
+/// Parse a register name, `V0` through `VF`
+fn register(s: &str) -> Result<usize, String> {
+    s.strip_prefix('V')
+        .or_else(|| s.strip_prefix('v'))
+        .filter(|x| x.len() == 1)
+        .and_then(|x| usize::from_str_radix(x, 16).ok())
+        .ok_or_else(|| format!("'{}' is not a valid register", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{disassemble_smart_to, disassemble_to};
+
+    #[test]
+    fn disassembly_round_trips() {
+        for opcode in 0..=u16::MAX {
+            let line = Instruction::from(opcode).to_string();
+            assert_eq!(
+                assemble(&line),
+                Ok(opcode.to_be_bytes().to_vec()),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn labels_resolve_to_addresses() {
+        let source = "start:\n  CALL sub ; forward reference\n  JMP start\nsub: RET\n";
+        assert_eq!(
+            assemble(source),
+            Ok(vec![0x22, 0x04, 0x12, 0x00, 0x00, 0xEE])
+        );
+    }
+
+    #[test]
+    fn disassembler_listings_round_trip() {
+        // A jump over two bytes of data to a subroutine call, then an odd byte at the end
+        let rom = [
+            0x12, 0x04, 0xF0, 0xAB, 0x22, 0x08, 0x12, 0x04, 0x00, 0xEE, 0x42,
+        ];
+        let mut listing = Vec::new();
+        disassemble_to(&rom, PROGRAM_START, 0, None, &mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert_eq!(assemble(&listing), Ok(rom.to_vec()), "{}", listing);
+
+        let mut listing = Vec::new();
+        disassemble_smart_to(&rom, PROGRAM_START, None, &mut listing).unwrap();
+        let listing = String::from_utf8(listing).unwrap();
+        assert!(listing.contains("DB"), "{}", listing);
+        assert!(listing.contains("L_0204:"), "{}", listing);
+        assert_eq!(assemble(&listing), Ok(rom.to_vec()), "{}", listing);
+    }
+
+    #[test]
+    fn undefined_labels_are_rejected() {
+        assert_eq!(
+            assemble("JMP nowhere"),
+            Result::Err("line 1: label 'nowhere' is not defined".into())
+        );
+    }
+}
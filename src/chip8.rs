@@ -1,50 +1,35 @@
-use std::ops::Index;
+use std::{collections::VecDeque, ops::Index};
 
-use rand::Rng;
+use log::debug;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::Instruction::*;
+use crate::font::{
+    glyph_address, BIG_FONT_ADDRESS, BIG_FONT_DATA, BIG_FONT_SIZE, FONT_ADDRESS, FONT_DATA,
+    FONT_SIZE,
+};
+use crate::instruction::Instruction::*;
 use crate::{framebuffer::Framebuffer, instruction::Instruction};
 
 /// Memory size in bytes
-const MEMORY_SIZE: usize = 4096;
-/// Program start address
+pub const MEMORY_SIZE: usize = 4096;
+/// Default program start address
 pub const PROGRAM_START: usize = 0x200;
 /// Display height in pixels
 pub const DISPLAY_HEIGHT: usize = 32;
 /// Display width in pixels
 pub const DISPLAY_WIDTH: usize = 64;
-/// Stack size
-const STACK_SIZE: usize = 16;
+/// Default stack size, which is the size of the stack of most interpreters
+pub const STACK_SIZE: usize = 16;
 /// Number of 8-bit general purpose registers
-const NUMBER_OF_REGISTERS: usize = 16;
+pub const NUMBER_OF_REGISTERS: usize = 16;
+/// Frequency (in Hz) at which the delay and sound timers are decremented
+pub const TIMER_FREQUENCY: u32 = 60;
 /// Number of keys on the keypad
 pub const KEYPAD_SIZE: usize = 16;
-/// Number of glyphs in the default font
-const GLYPH_COUNT: usize = 16;
-/// Size (in bytes) of the glyphs in the default font
-const GLYPH_SIZE: usize = 5;
-/// Default font
-const FONT_DATA: [u8; GLYPH_SIZE * GLYPH_COUNT] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
+/// Number of memory writes kept by the write log
+pub const WRITE_LOG_SIZE: usize = 256;
 /// CHIP-8 virtual machine
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Chip8 {
     /// RAM
     mem: [u8; MEMORY_SIZE],
@@ -61,17 +46,31 @@ pub struct Chip8 {
     /// Sound timer
     pub st: u8,
     /// Address stack
-    stack: [usize; STACK_SIZE],
+    stack: Vec<usize>,
     /// Stack pointer
     sp: usize,
     /// Keypad
     pub keypad: Keypad,
     /// Quirks
     quirks: Quirks,
+    /// Register that receives the next pressed or released key, while waiting for a key
+    /// (opcode 0xFx0A)
+    key_register: Option<usize>,
+    /// Random number generator used by the random number opcode (0xCxkk)
+    rng: StdRng,
+    /// The most recent memory writes, as `(address, pc)`, oldest first.  `None` unless
+    /// logging is enabled.
+    write_log: Option<VecDeque<(usize, usize)>>,
+    /// Number of clear screen instructions (opcode 0x00E0) ignored so far.  `None` unless
+    /// they are ignored.
+    ignored_clears: Option<u64>,
 }
 
+/// Names of the platforms that quirk presets are available for
+pub const PRESETS: [&str; 4] = ["chip8", "schip", "xochip", "cosmac"];
+
 /// CHIP-8 implementation quirks
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct Quirks {
     /// The AND, OR, and XOR opcodes (0x8xy1, 0x8xy2, and 0x8xy3) reset the flags
     /// register to zero
@@ -86,58 +85,292 @@ pub struct Quirks {
     /// The jump with offset opcode (0xBnnn) uses Vx instead of V0, where x is the
     /// largest nibble of nnn
     pub jumping: bool,
+    /// The wait for key opcode (0xFx0A) completes when a key is released, instead of when
+    /// a key is pressed
+    pub key_wait_release: bool,
+    /// The draw opcode (0xDxyn) sets the flags register to the number of sprite rows that
+    /// collided or were clipped at the bottom of the screen, instead of 0 or 1
+    pub collision_rows: bool,
+    /// The add to index opcode (0xFx1E) sets the flags register to 1 when the index register
+    /// overflows past the end of memory, and 0 otherwise
+    pub addi_overflow: bool,
+    /// The draw opcode (0xDxyn) draws nothing when the starting coordinate is off the
+    /// screen, instead of wrapping it around to the other side
+    pub clip_origin: bool,
+}
+
+impl Quirks {
+    /// Quirks of a well-known platform, or `None` if `name` isn't one of `PRESETS`:
+    ///  - chip8 - modern CHIP-8 interpreters (no quirks)
+    ///  - schip - SUPER-CHIP 1.1
+    ///  - xochip - XO-CHIP
+    ///  - cosmac - the original CHIP-8 interpreter on the COSMAC VIP
+    pub fn preset(name: &str) -> Option<Self> {
+        let quirks = match name {
+            "chip8" => Self::default(),
+            "schip" => Self {
+                jumping: true,
+                collision_rows: true,
+                ..Self::default()
+            },
+            "xochip" => Self {
+                memory: true,
+                wrap: true,
+                shifting: true,
+                ..Self::default()
+            },
+            "cosmac" => Self {
+                vf_reset: true,
+                memory: true,
+                shifting: true,
+                key_wait_release: true,
+                ..Self::default()
+            },
+            _ => return None,
+        };
+        Some(quirks)
+    }
 }
 
 impl Chip8 {
-    pub fn new(rom: &[u8], quirks: Quirks) -> Result<Self, String> {
-        if rom.len() >= MEMORY_SIZE - PROGRAM_START {
+    /// Load `rom` into memory at `start`, where execution begins
+    pub fn new(rom: &[u8], quirks: Quirks, start: usize) -> Result<Self, String> {
+        if start >= MEMORY_SIZE || !start.is_multiple_of(2) {
+            return Result::Err(format!("{:#05X} is not a valid start address", start));
+        }
+        if rom.len() >= MEMORY_SIZE - start {
             return Result::Err("program is too large to fit in memory".into());
         }
 
         let mut mem: [u8; MEMORY_SIZE] = [0; MEMORY_SIZE];
-        mem[0..FONT_DATA.len()].copy_from_slice(&FONT_DATA);
-        mem[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        mem[FONT_ADDRESS..FONT_ADDRESS + FONT_SIZE].copy_from_slice(&FONT_DATA);
+        mem[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + BIG_FONT_SIZE].copy_from_slice(&BIG_FONT_DATA);
+        mem[start..start + rom.len()].copy_from_slice(rom);
 
         Ok(Self {
             mem,
             fb: Framebuffer::new(),
             v: [0; NUMBER_OF_REGISTERS],
             i: 0,
-            pc: PROGRAM_START,
+            pc: start,
             dt: 0,
             st: 0,
-            stack: [0; STACK_SIZE],
+            stack: vec![0; STACK_SIZE],
             sp: 0,
             keypad: Keypad::new(),
             quirks,
+            key_register: None,
+            rng: StdRng::from_entropy(),
+            write_log: None,
+            ignored_clears: None,
         })
     }
 
-    /// Fetch, decode, and execute the next instruction
-    pub fn step(&mut self) {
+    /// Replace the default font with `font`, which holds 16 glyphs of 5 bytes each
+    pub fn set_font(&mut self, font: &[u8]) -> Result<(), String> {
+        if font.len() != FONT_SIZE {
+            return Result::Err(format!(
+                "expected {} bytes, found {}",
+                FONT_SIZE,
+                font.len()
+            ));
+        }
+        self.mem[FONT_ADDRESS..FONT_ADDRESS + FONT_SIZE].copy_from_slice(font);
+        Ok(())
+    }
+
+    /// Overwrite memory starting at `addr` with `bytes`, e.g., to modify the program after
+    /// it is loaded
+    pub fn patch(&mut self, addr: usize, bytes: &[u8]) -> Result<(), String> {
+        if addr + bytes.len() > MEMORY_SIZE {
+            return Result::Err(format!(
+                "{} bytes at {:#05X} do not fit in memory",
+                bytes.len(),
+                addr
+            ));
+        }
+        self.mem[addr..addr + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Change the number of addresses the stack can hold.  Most interpreters only hold 16,
+    /// so larger stacks are non-standard.  Call this before running the program.
+    pub fn set_stack_size(&mut self, size: usize) {
+        self.stack.resize(size.max(self.sp), 0);
+    }
+
+    /// Seed the random number generator, so that the random number opcode (0xCxkk) returns
+    /// the same sequence of numbers every run
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Keep a log of the last `WRITE_LOG_SIZE` memory writes and the addresses of the
+    /// instructions that made them
+    pub fn log_writes(&mut self) {
+        self.write_log = Some(VecDeque::with_capacity(WRITE_LOG_SIZE));
+    }
+
+    /// Logged memory writes as `(address, pc)`, oldest first.  Empty unless `log_writes`
+    /// was called.
+    pub fn write_log(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.write_log.iter().flatten().copied()
+    }
+
+    /// Forget all logged memory writes
+    pub fn clear_write_log(&mut self) {
+        if let Some(log) = &mut self.write_log {
+            log.clear();
+        }
+    }
+
+    /// Ignore the clear screen instruction (opcode 0x00E0), so that everything drawn stays
+    /// visible.  This breaks nearly every program, but helps with studying how they draw.
+    pub fn ignore_clears(&mut self) {
+        self.ignored_clears = Some(0);
+    }
+
+    /// Number of clear screen instructions ignored so far, or `None` unless `ignore_clears`
+    /// was called
+    pub fn ignored_clears(&self) -> Option<u64> {
+        self.ignored_clears
+    }
+
+    /// Fetch, decode, and execute the next instruction.  Returns the executed instruction,
+    /// or an error (prefixed with the address of the instruction) if the instruction cannot
+    /// be executed.  Nothing is executed while waiting for a key release.
+    pub fn step(&mut self) -> Result<Instruction, String> {
+        self.step_with_hook(|_, _| {})
+    }
+
+    /// Like `step`, but calls `hook` with each instruction after it is decoded and before
+    /// it is executed, so the hook sees the state of the virtual machine before the
+    /// instruction runs.  The hook isn't called while waiting for a key.
+    pub fn step_with_hook(
+        &mut self,
+        mut hook: impl FnMut(&Instruction, &Chip8),
+    ) -> Result<Instruction, String> {
+        if let Some(x) = self.key_register {
+            let key = if self.quirks.key_wait_release {
+                self.keypad.key_released.take()
+            } else {
+                self.keypad.key_pressed.take()
+            };
+            match key {
+                Some(key) => {
+                    self.v[x] = key;
+                    self.keypad.wait = false;
+                    self.key_register = None;
+                }
+                // Still waiting, so nothing is executed
+                None => return Ok(Ldk(x)),
+            }
+        }
+
+        let pc = self.pc;
         let instr = Instruction::from(self.fetch());
-        self.execute(instr);
+        hook(&instr, self);
+        let result = self.execute(instr);
+        // Like the address bus, the program counter wraps around past the end of memory
+        self.pc %= MEMORY_SIZE;
+        result.map_err(|e| format!("{:#06X}: {}", pc, e))?;
+        Ok(instr)
+    }
+
+    /// Whether execution is suspended until a key is pressed or released (opcode 0xFx0A).
+    /// Stepping while waiting does nothing.
+    pub fn waiting_for_key(&self) -> bool {
+        let key = if self.quirks.key_wait_release {
+            self.keypad.key_released
+        } else {
+            self.keypad.key_pressed
+        };
+        self.key_register.is_some() && key.is_none()
+    }
+
+    /// Decode the next instruction without executing it
+    pub fn next_instruction(&self) -> Instruction {
+        Instruction::from(self.fetch())
     }
 
+    /// Execute exactly `n` instructions, stopping early if an instruction cannot be
+    /// executed
+    pub fn run_cycles(&mut self, n: usize) -> Result<(), String> {
+        for _ in 0..n {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// General purpose registers, V0 through VF
+    pub fn v(&self) -> &[u8; NUMBER_OF_REGISTERS] {
+        &self.v
+    }
+
+    /// Index register
+    pub fn i(&self) -> usize {
+        self.i
+    }
+
+    /// Program counter
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Stack pointer
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// Quirks the virtual machine follows
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Follow different quirks from the next instruction on
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Addresses on the stack, from the bottom of the stack to the top
+    pub fn stack(&self) -> &[usize] {
+        &self.stack[..self.sp]
+    }
+
+    /// RAM
+    pub fn mem(&self) -> &[u8; MEMORY_SIZE] {
+        &self.mem
+    }
+
+    /// Decrement the delay and sound timers, if they are non-zero
+    pub fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    /// Read the opcode at the program counter.  An opcode at the last address of memory
+    /// continues at the first address.
     fn fetch(&self) -> u16 {
-        assert!(
-            self.pc + 1 < MEMORY_SIZE,
-            "Attempted to read outside of memory bounds"
-        );
-        u16::from_be_bytes([self.mem[self.pc], self.mem[self.pc + 1]])
+        u16::from_be_bytes([
+            self.mem[self.pc % MEMORY_SIZE],
+            self.mem[(self.pc + 1) % MEMORY_SIZE],
+        ])
     }
 
-    fn execute(&mut self, instr: Instruction) {
+    fn execute(&mut self, instr: Instruction) -> Result<(), String> {
         // Increment program counter as this is the default for most instructions
         self.pc += 2;
 
         match instr {
             Sys(_) => {}
-            Cls => {
-                self.fb.clear();
-            }
+            Cls => match &mut self.ignored_clears {
+                Some(count) => *count += 1,
+                None => self.fb.clear(),
+            },
             Ret => {
-                assert_ne!(self.sp, 0, "Stack underflow");
+                if self.sp == 0 {
+                    return Result::Err("stack underflow".into());
+                }
                 self.sp -= 1;
                 self.pc = self.stack[self.sp] + 2;
             }
@@ -145,7 +378,9 @@ impl Chip8 {
                 self.pc = nnn;
             }
             Call(nnn) => {
-                assert_ne!(self.sp, STACK_SIZE, "Stack overflow");
+                if self.sp == self.stack.len() {
+                    return Result::Err("stack overflow".into());
+                }
                 self.stack[self.sp] = self.pc - 2;
                 self.sp += 1;
                 self.pc = nnn;
@@ -195,6 +430,8 @@ impl Chip8 {
             Add(x, y) => {
                 let (value, overflow) = self.v[x].overflowing_add(self.v[y]);
                 self.v[x] = value;
+                // The flag is written last, so VF holds the carry flag, instead of the result,
+                // even if x is F
                 if overflow {
                     self.v[0xF] = 1;
                 } else {
@@ -204,6 +441,8 @@ impl Chip8 {
             Sub(x, y) => {
                 let (value, overflow) = self.v[x].overflowing_sub(self.v[y]);
                 self.v[x] = value;
+                // The flag is written last, so VF holds the borrow flag, instead of the result,
+                // even if x is F
                 if overflow {
                     self.v[0xF] = 0;
                 } else {
@@ -211,16 +450,20 @@ impl Chip8 {
                 }
             }
             Shr(x, y) => {
-                if self.quirks.shifting {
-                    self.v[x] = self.v[y]
-                }
-                let flag = self.v[x] & 0x1;
-                self.v[x] >>= 1;
-                self.v[0xF] = flag;
+                let value = if self.quirks.shifting {
+                    self.v[y]
+                } else {
+                    self.v[x]
+                };
+                self.v[x] = value >> 1;
+                // The flag is written last, so VF holds the shifted-out bit even if x is F
+                self.v[0xF] = value & 0x1;
             }
             Subr(x, y) => {
                 let (value, overflow) = self.v[y].overflowing_sub(self.v[x]);
                 self.v[x] = value;
+                // The flag is written last, so VF holds the borrow flag, instead of the result,
+                // even if x is F
                 if overflow {
                     self.v[0xF] = 0;
                 } else {
@@ -228,12 +471,14 @@ impl Chip8 {
                 }
             }
             Shl(x, y) => {
-                if self.quirks.shifting {
-                    self.v[x] = self.v[y]
-                }
-                let flag = (self.v[x] & 0b1000_0000) >> 7;
-                self.v[x] <<= 1;
-                self.v[0xF] = flag;
+                let value = if self.quirks.shifting {
+                    self.v[y]
+                } else {
+                    self.v[x]
+                };
+                self.v[x] = value << 1;
+                // The flag is written last, so VF holds the shifted-out bit even if x is F
+                self.v[0xF] = (value & 0b1000_0000) >> 7;
             }
             Skne(x, y) => {
                 if self.v[x] != self.v[y] {
@@ -245,6 +490,8 @@ impl Chip8 {
             }
             Jmpz(nnn) => {
                 if self.quirks.jumping {
+                    // nnn only has 12 bits, so x is at most 0xF and always a valid register
+                    // (e.g., 0xB345 jumps to 0x345 + V3)
                     let x = nnn >> 8;
                     self.pc = nnn + usize::from(self.v[x]);
                 } else {
@@ -252,29 +499,43 @@ impl Chip8 {
                 }
             }
             Rnd(x, nn) => {
-                self.v[x] = rand::thread_rng().gen::<u8>() & nn;
+                self.v[x] = self.rng.gen::<u8>() & nn;
             }
             Draw(x, y, n) => {
-                if self.fb.draw(
-                    self.v[x],
-                    self.v[y],
-                    n,
-                    &self.mem[self.i..self.i + usize::from(n)],
-                    self.quirks.wrap,
-                ) {
-                    self.v[0xF] = 1;
-                } else {
+                let off_screen = usize::from(self.v[x]) >= DISPLAY_WIDTH
+                    || usize::from(self.v[y]) >= DISPLAY_HEIGHT;
+                if self.quirks.clip_origin && off_screen {
                     self.v[0xF] = 0;
+                    return Ok(());
                 }
+                let sprite = self
+                    .mem
+                    .get(self.i..self.i + usize::from(n))
+                    .ok_or("attempted to read outside of memory bounds")?;
+                let collisions = self
+                    .fb
+                    .draw(self.v[x], self.v[y], n, sprite, self.quirks.wrap);
+                self.v[0xF] = if self.quirks.collision_rows {
+                    // Rows clipped at the bottom of the screen also count
+                    let bottom = usize::from(self.v[y]) % DISPLAY_HEIGHT + usize::from(n);
+                    let clipped = if self.quirks.wrap {
+                        0
+                    } else {
+                        bottom.saturating_sub(DISPLAY_HEIGHT)
+                    };
+                    collisions + clipped as u8
+                } else {
+                    u8::from(collisions > 0)
+                };
             }
             Skp(x) => {
-                let key = self.v[x];
+                let key = valid_key(self.v[x])?;
                 if self.keypad[key] {
                     self.pc += 2;
                 }
             }
             Sknp(x) => {
-                let key = self.v[x];
+                let key = valid_key(self.v[x])?;
                 if !self.keypad[key] {
                     self.pc += 2;
                 }
@@ -283,18 +544,11 @@ impl Chip8 {
                 self.v[x] = self.dt;
             }
             Ldk(x) => {
-                if self.keypad.wait {
-                    if let Some(key) = self.keypad.key_released {
-                        self.v[x] = key;
-                        self.keypad.wait = false;
-                        self.keypad.key_released = None;
-                    } else {
-                        self.pc -= 2;
-                    }
-                } else {
-                    self.keypad.wait = true;
-                    self.pc -= 2;
-                }
+                // Execution is suspended until a key is pressed or released; see `step`
+                self.keypad.wait = true;
+                self.keypad.key_pressed = None;
+                self.keypad.key_released = None;
+                self.key_register = Some(x);
             }
             Lddt(x) => {
                 self.dt = self.v[x];
@@ -303,43 +557,43 @@ impl Chip8 {
                 self.st = self.v[x];
             }
             Addi(x) => {
-                self.i += usize::from(self.v[x]);
+                let i = self.i + usize::from(self.v[x]);
+                // The index register can only address memory, so it wraps around past the end
+                self.i = i % MEMORY_SIZE;
+                if self.quirks.addi_overflow {
+                    self.v[0xF] = u8::from(i >= MEMORY_SIZE);
+                }
             }
             Font(x) => {
-                let digit = usize::from(self.v[x]);
-                assert!(
-                    digit < GLYPH_COUNT,
-                    "{:#X} is not a valid glyph in the default font",
-                    digit
-                );
-                self.i = GLYPH_SIZE * digit;
+                let digit = self.v[x];
+                self.i = glyph_address(digit, false)
+                    .ok_or_else(|| format!("{:#X} is not a valid glyph in the font", digit))?;
             }
             Bcd(x) => {
-                assert!(
-                    self.i + 2 < MEMORY_SIZE,
-                    "Attempted to write outside of memory bounds"
-                );
-                self.mem[self.i] = self.v[x] / 100;
-                self.mem[self.i + 1] = (self.v[x] / 10) % 10;
-                self.mem[self.i + 2] = self.v[x] % 10;
+                if self.i + 2 >= MEMORY_SIZE {
+                    return Result::Err("attempted to write outside of memory bounds".into());
+                }
+                self.write(self.i, self.v[x] / 100);
+                self.write(self.i + 1, (self.v[x] / 10) % 10);
+                self.write(self.i + 2, self.v[x] % 10);
             }
             Sreg(x) => {
-                assert!(
-                    self.i + x < MEMORY_SIZE,
-                    "Attempted to write outside of memory bounds"
-                );
+                if self.i + x >= MEMORY_SIZE {
+                    return Result::Err("attempted to write outside of memory bounds".into());
+                }
+                // VF is saved with the value it holds when this instruction runs, so right
+                // after an arithmetic instruction, it's the flag rather than the result
                 for offset in 0..=x {
-                    self.mem[self.i + offset] = self.v[offset];
+                    self.write(self.i + offset, self.v[offset]);
                 }
                 if self.quirks.memory {
                     self.i += x + 1;
                 }
             }
             Lreg(x) => {
-                assert!(
-                    self.i + x < MEMORY_SIZE,
-                    "Attempted to read outside of memory bounds"
-                );
+                if self.i + x >= MEMORY_SIZE {
+                    return Result::Err("attempted to read outside of memory bounds".into());
+                }
                 for offset in 0..=x {
                     self.v[offset] = self.mem[self.i + offset];
                 }
@@ -347,19 +601,49 @@ impl Chip8 {
                     self.i += x + 1;
                 }
             }
-            Err(_) => {}
+            Err(opcode) => debug!(
+                "{:#06X}: unknown instruction {:#06X} ignored",
+                self.pc - 2,
+                opcode
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Store `value` at `addr`, logging the write if enabled.  Only called while executing
+    /// an instruction, after the program counter is incremented.
+    fn write(&mut self, addr: usize, value: u8) {
+        self.mem[addr] = value;
+        if let Some(log) = &mut self.write_log {
+            if log.len() == WRITE_LOG_SIZE {
+                log.pop_front();
+            }
+            log.push_back((addr, self.pc - 2));
         }
     }
 }
 
-#[derive(Debug)]
+/// Check that `key` is on the keypad
+fn valid_key(key: u8) -> Result<u8, String> {
+    if usize::from(key) < KEYPAD_SIZE {
+        Ok(key)
+    } else {
+        Result::Err(format!("{:#X} is not a valid key", key))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Keypad {
     /// Track which keys are pressed
     keys: [bool; KEYPAD_SIZE],
-    /// Set to `true` when executing the "wait for key release" instruction (opcode 0xFx0A)
+    /// Set to `true` when executing the "wait for key" instruction (opcode 0xFx0A)
     wait: bool,
-    /// The value of the key that was finally released while executing the "wait for key
-    /// release" instruction (opcode 0xFx0A)
+    /// The value of the key that was finally pressed while executing the "wait for key"
+    /// instruction (opcode 0xFx0A)
+    key_pressed: Option<u8>,
+    /// The value of the key that was finally released while executing the "wait for key"
+    /// instruction (opcode 0xFx0A)
     key_released: Option<u8>,
 }
 
@@ -373,18 +657,41 @@ impl Index<u8> for Keypad {
     }
 }
 
+impl Default for Keypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keypad {
     pub const fn new() -> Self {
         Self {
             keys: [false; KEYPAD_SIZE],
             wait: false,
+            key_pressed: None,
             key_released: None,
         }
     }
 
-    pub fn key_pressed(&mut self, key: u8) {
-        let key = usize::from(key);
+    /// Whether `key` is held down.  Keys that aren't on the keypad never are.
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys.get(usize::from(key)).copied().unwrap_or(false)
+    }
+
+    /// Every key that is held down, in ascending order
+    pub fn pressed_keys(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..)
+            .zip(self.keys)
+            .filter_map(|(key, pressed)| pressed.then_some(key))
+    }
+
+    pub fn key_pressed(&mut self, key_pressed: u8) {
+        let key = usize::from(key_pressed);
         assert!(key < KEYPAD_SIZE, "{:#X} is not a valid key", key);
+        // Keys held down while the wait starts, or repeated by the keyboard, don't count
+        if self.wait && !self.keys[key] {
+            self.key_pressed = Some(key_pressed);
+        }
         self.keys[key] = true;
     }
 
@@ -397,3 +704,271 @@ impl Keypad {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load `program`, given as opcodes, at the default start address
+    fn load(program: &[u16], quirks: Quirks) -> Chip8 {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        Chip8::new(&rom, quirks, PROGRAM_START).unwrap()
+    }
+
+    /// Load `program` and execute one instruction for each of its opcodes
+    fn run(program: &[u16], quirks: Quirks) -> Chip8 {
+        let mut chip = load(program, quirks);
+        chip.run_cycles(program.len()).unwrap();
+        chip
+    }
+
+    #[test]
+    fn runs_exactly_n_cycles() {
+        // V0 = 5 + 7, then its digits are stored at 0x300
+        let program = [0x6005, 0x6107, 0x8014, 0xA300, 0xF033];
+        let mut chip = load(&program, Quirks::default());
+        chip.run_cycles(3).unwrap();
+        assert_eq!(chip.pc(), 0x206);
+        assert_eq!(chip.v()[0], 12);
+        assert_eq!(chip.i(), 0);
+
+        chip.run_cycles(2).unwrap();
+        assert_eq!(chip.pc(), 0x20A);
+        assert_eq!(chip.i(), 0x300);
+        assert_eq!(chip.mem()[0x300..0x303], [0, 1, 2]);
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        // RET with an empty stack, followed by LDB V0, 1
+        let mut chip = load(&[0x00EE, 0x6001], Quirks::default());
+        assert!(chip.run_cycles(2).is_err());
+        assert_eq!(chip.v()[0], 0);
+    }
+
+    #[test]
+    fn shifting_vf_keeps_the_shifted_out_bit() {
+        for shifting in [false, true] {
+            let quirks = Quirks {
+                shifting,
+                ..Quirks::default()
+            };
+            // VF = 0x80 and V1 = 0x7F, so the flag differs from the result and between Vx and
+            // Vy
+            let chip = run(&[0x6F80, 0x617F, 0x8F16], quirks);
+            assert_eq!(
+                chip.v()[0xF],
+                u8::from(shifting),
+                "8F16, shifting: {}",
+                shifting
+            );
+            let chip = run(&[0x6F80, 0x617F, 0x8F1E], quirks);
+            assert_eq!(
+                chip.v()[0xF],
+                u8::from(!shifting),
+                "8F1E, shifting: {}",
+                shifting
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic_into_vf_keeps_the_flag() {
+        let vf = |program| run(program, Quirks::default()).v()[0xF];
+        // 0xFF + 0x02 carries, and 0x01 + 0x02 doesn't
+        assert_eq!(vf(&[0x6FFF, 0x6102, 0x8F14]), 1);
+        assert_eq!(vf(&[0x6F01, 0x6102, 0x8F14]), 0);
+        // 0x01 - 0x02 borrows, and 0x05 - 0x02 doesn't
+        assert_eq!(vf(&[0x6F01, 0x6102, 0x8F15]), 0);
+        assert_eq!(vf(&[0x6F05, 0x6102, 0x8F15]), 1);
+        // 0x02 - 0x05 borrows, and 0x02 - 0x00 doesn't
+        assert_eq!(vf(&[0x6F05, 0x6102, 0x8F17]), 0);
+        assert_eq!(vf(&[0x6F00, 0x6102, 0x8F17]), 1);
+    }
+
+    #[test]
+    fn key_wait_suspends_execution() {
+        // Wait for a key into V3, then LDB V4, 1
+        let mut chip = load(&[0xF30A, 0x6401], Quirks::default());
+        chip.step().unwrap();
+        assert!(chip.waiting_for_key());
+        chip.run_cycles(10).unwrap();
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.v()[4], 0);
+
+        // Pressed and released between two steps, e.g., within a single batch
+        chip.keypad.key_pressed(0xA);
+        chip.keypad.key_released(0xA);
+        assert!(!chip.waiting_for_key());
+        chip.step().unwrap();
+        assert_eq!(chip.v()[3], 0xA);
+        assert_eq!(chip.v()[4], 1);
+        assert_eq!(chip.pc(), 0x204);
+    }
+
+    #[test]
+    fn key_wait_ignores_keys_already_held() {
+        let mut chip = load(&[0xF30A], Quirks::default());
+        chip.keypad.key_pressed(0x1);
+        chip.step().unwrap();
+        // Repeated by the keyboard while held
+        chip.keypad.key_pressed(0x1);
+        assert!(chip.waiting_for_key());
+        chip.keypad.key_pressed(0x2);
+        chip.step().unwrap();
+        assert_eq!(chip.v()[3], 0x2);
+    }
+
+    #[test]
+    fn key_wait_completes_on_press_or_release() {
+        for key_wait_release in [false, true] {
+            let quirks = Quirks {
+                key_wait_release,
+                ..Quirks::default()
+            };
+            let mut chip = load(&[0xF30A], quirks);
+            chip.step().unwrap();
+            chip.keypad.key_pressed(0x7);
+            assert_eq!(chip.waiting_for_key(), key_wait_release);
+            chip.keypad.key_released(0x7);
+            assert!(!chip.waiting_for_key());
+            chip.step().unwrap();
+            assert_eq!(chip.v()[3], 0x7);
+        }
+    }
+
+    #[test]
+    fn counts_colliding_rows() {
+        let schip = Quirks::preset("schip").unwrap();
+        // The glyph for 0 has 5 rows, which all collide when drawn twice
+        let program = [0xA000, 0xD005, 0xD005];
+        assert_eq!(run(&program, schip).v()[0xF], 5);
+        assert_eq!(run(&program, Quirks::default()).v()[0xF], 1);
+
+        // At y = 30, 2 rows are drawn and 3 are clipped at the bottom
+        let program = [0xA000, 0x611E, 0xD215, 0x80F0, 0xD215];
+        let chip = run(&program, schip);
+        assert_eq!(chip.v()[0], 3);
+        assert_eq!(chip.v()[0xF], 5);
+        let chip = run(&program, Quirks::default());
+        assert_eq!(chip.v()[0], 0);
+        assert_eq!(chip.v()[0xF], 1);
+    }
+
+    #[test]
+    fn add_to_index_overflow() {
+        for addi_overflow in [false, true] {
+            let quirks = Quirks {
+                addi_overflow,
+                ..Quirks::default()
+            };
+            // 0xFFF + 2 overflows, and wraps around to 0x001
+            let chip = run(&[0xAFFF, 0x6002, 0xF01E], quirks);
+            assert_eq!(chip.i(), 0x001);
+            assert_eq!(chip.v()[0xF], u8::from(addi_overflow));
+            // 0x100 + 2 doesn't overflow, which clears VF with the quirk
+            let chip = run(&[0xA100, 0x6002, 0x6F01, 0xF01E], quirks);
+            assert_eq!(chip.i(), 0x102);
+            assert_eq!(chip.v()[0xF], u8::from(!addi_overflow));
+        }
+    }
+
+    #[test]
+    fn drawing_past_the_end_of_memory_fails() {
+        // 15 rows from 0xFFE would read past the end of memory
+        let mut chip = load(&[0xAFFE, 0xD01F], Quirks::default());
+        chip.step().unwrap();
+        assert!(chip.step().is_err());
+        assert!(chip.fb.pixels().all(|(_, _, set)| !set));
+    }
+
+    #[test]
+    fn jump_with_offset_register() {
+        // V0 = 0x10 and V3 = 0x20, then BNNN with nnn = 0x345
+        let program = [0x6010, 0x6320, 0xB345];
+        assert_eq!(run(&program, Quirks::default()).pc(), 0x355);
+        let quirks = Quirks {
+            jumping: true,
+            ..Quirks::default()
+        };
+        assert_eq!(run(&program, quirks).pc(), 0x365);
+    }
+
+    #[test]
+    fn clip_origin_draws_nothing_off_screen() {
+        // The top row of the glyph for 0 (0xF0) at x = 70, which wraps to x = 6
+        let program = [0x6046, 0xA000, 0xD011];
+        let chip = run(&program, Quirks::default());
+        let set: Vec<_> = chip.fb.pixels().filter(|&(_, _, set)| set).collect();
+        assert_eq!(
+            set,
+            [(6, 0, true), (7, 0, true), (8, 0, true), (9, 0, true)]
+        );
+
+        let quirks = Quirks {
+            clip_origin: true,
+            ..Quirks::default()
+        };
+        let chip = run(&program, quirks);
+        assert!(chip.fb.pixels().all(|(_, _, set)| !set));
+        assert_eq!(chip.v()[0xF], 0);
+    }
+
+    #[test]
+    fn hook_sees_each_instruction_before_it_runs() {
+        // V0 = 5, then V0 += 1 in a loop
+        let mut chip = load(&[0x6005, 0x7001, 0x1202], Quirks::default());
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            chip.step_with_hook(|instr, chip| seen.push((instr.mnemonic(), chip.v()[0])))
+                .unwrap();
+        }
+        assert_eq!(
+            seen,
+            [("LDB", 0), ("ADDB", 5), ("JMP", 6), ("ADDB", 6), ("JMP", 7)]
+        );
+    }
+
+    #[test]
+    fn program_counter_wraps_around() {
+        // LDB V0, 5 at the last instruction of memory
+        let mut chip = load(&[0x1FFE], Quirks::default());
+        chip.patch(0xFFE, &[0x60, 0x05]).unwrap();
+        chip.run_cycles(2).unwrap();
+        assert_eq!(chip.v()[0], 5);
+        assert_eq!(chip.pc(), 0);
+
+        // An opcode at the last address continues at the first, which holds the font
+        let mut chip = load(&[0x1FFF], Quirks::default());
+        chip.patch(0xFFF, &[0x60]).unwrap();
+        chip.run_cycles(2).unwrap();
+        assert_eq!(chip.v()[0], FONT_DATA[0]);
+        assert_eq!(chip.pc(), 1);
+    }
+
+    #[test]
+    fn saving_after_arithmetic_stores_the_flag() {
+        // V0 = 0xFF + 0x02, which carries, then V0 through VF are stored at 0x300
+        let chip = run(&[0x60FF, 0x6102, 0x8014, 0xA300, 0xFF55], Quirks::default());
+        assert_eq!(chip.mem()[0x300], 0x01);
+        assert_eq!(chip.mem()[0x301], 0x02);
+        assert_eq!(chip.mem()[0x30F], 1);
+
+        // Adding into VF leaves the flag, rather than the sum, to be stored
+        let chip = run(&[0x6F01, 0x6102, 0x8F14, 0xA300, 0xFF55], Quirks::default());
+        assert_eq!(chip.mem()[0x30F], 0);
+    }
+
+    #[test]
+    fn reads_the_keypad() {
+        let mut keypad = Keypad::new();
+        for key in [0xA, 0x1, 0xF] {
+            keypad.key_pressed(key);
+        }
+        keypad.key_released(0xF);
+        assert_eq!(keypad.pressed_keys().collect::<Vec<_>>(), [0x1, 0xA]);
+        assert!(keypad.is_pressed(0xA));
+        assert!(!keypad.is_pressed(0xF));
+        assert!(!keypad.is_pressed(0x10));
+    }
+}
@@ -0,0 +1,48 @@
+use std::{collections::HashMap, fs::read_to_string, path::Path};
+
+use serde::Deserialize;
+
+/// Settings loaded from a TOML configuration file.  Every setting is optional, and
+/// mirrors the command line option of the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub fps: Option<u16>,
+    pub ipf: Option<u16>,
+    pub scale: Option<u32>,
+    pub color: Option<String>,
+    pub background: Option<String>,
+    pub theme: Option<String>,
+    pub pitch: Option<u16>,
+    pub waveform: Option<String>,
+    pub volume: Option<f32>,
+    pub display_wait: Option<bool>,
+    pub vblank_wait: Option<bool>,
+    pub profile: Option<String>,
+    pub quirks: QuirksConfig,
+    /// Key bindings, from CHIP-8 keys to scancode names
+    pub keymap: HashMap<String, String>,
+}
+
+/// The `[quirks]` table of a configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirksConfig {
+    pub vf_reset: Option<bool>,
+    pub memory: Option<bool>,
+    pub wrap: Option<bool>,
+    pub shifting: Option<bool>,
+    pub jumping: Option<bool>,
+    pub key_wait_release: Option<bool>,
+    pub collision_rows: Option<bool>,
+    pub addi_overflow: Option<bool>,
+    pub clip_origin: Option<bool>,
+}
+
+impl Config {
+    /// Load a configuration file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = read_to_string(path).map_err(|e| e.to_string())?;
+        toml::from_str(&contents).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,245 @@
+use std::{collections::HashSet, io, io::Write, ops::Range};
+
+use crate::instruction::Instruction;
+use crate::instruction::Instruction::*;
+
+/// A single line of disassembly
+enum Line {
+    /// An instruction, as its opcode
+    Code(u16),
+    /// A byte of data
+    Data(u8),
+}
+
+/// Write the disassembly of `rom`, loaded at `start`, to `w`, decoding every 2-byte chunk
+/// after the first `offset` bytes as an instruction.  Those bytes are written as data, so
+/// an odd `offset` realigns the decoding, e.g., after a data section of odd length.  If
+/// `range` is given, only the addresses inside it are written.
+pub fn disassemble_to(
+    rom: &[u8],
+    start: usize,
+    offset: usize,
+    range: Option<Range<usize>>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let (data, code) = rom.split_at(offset.min(rom.len()));
+    let data = (start..)
+        .zip(data)
+        .map(|(addr, &byte)| (addr, Line::Data(byte)));
+    let code = code.chunks(2).enumerate().map(|(i, x)| {
+        let addr = start + offset + 2 * i;
+        match *x {
+            [high, low] => (addr, Line::Code(u16::from_be_bytes([high, low]))),
+            // The last byte of an odd-length program can't be an instruction
+            _ => (addr, Line::Data(x[0])),
+        }
+    });
+    let listing: Vec<(usize, Line)> = data.chain(code).collect();
+    write_listing(&listing, range, w)
+}
+
+/// Write the disassembly of `rom`, loaded at `start`, to `w`, only decoding bytes that are
+/// reachable as code from the start of the program.  All other bytes are written as data.
+/// If `range` is given, only the addresses inside it are written.
+pub fn disassemble_smart_to(
+    rom: &[u8],
+    start: usize,
+    range: Option<Range<usize>>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let code = find_code(rom, start);
+    let mut listing = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = start + offset;
+        if code[offset] {
+            let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+            listing.push((addr, Line::Code(opcode)));
+            offset += 2;
+        } else {
+            listing.push((addr, Line::Data(rom[offset])));
+            offset += 1;
+        }
+    }
+    write_listing(&listing, range, w)
+}
+
+/// Write a hex dump of `bytes`, starting at address `base`, to `w`.  Each line shows the
+/// address, 16 bytes in hexadecimal, and the same bytes as ASCII, with `.` in place of
+/// unprintable characters.
+pub fn hexdump_to(bytes: &[u8], base: usize, w: &mut impl Write) -> io::Result<()> {
+    for (row, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    char::from(b)
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            w,
+            "{:#06X}: {:<47}  |{}|",
+            base + 16 * row,
+            hex.join(" "),
+            ascii
+        )?;
+    }
+    Ok(())
+}
+
+/// Write each line of `listing` to `w`, showing the address, the raw opcode or byte, and
+/// its disassembly.  Addresses targeted by jumps and calls are given
+/// a label, which is written before the target and used in place of the address.  Lines
+/// outside of `range` are skipped.
+fn write_listing(
+    listing: &[(usize, Line)],
+    range: Option<Range<usize>>,
+    w: &mut impl Write,
+) -> io::Result<()> {
+    let listing: Vec<&(usize, Line)> = listing
+        .iter()
+        .filter(|(addr, _)| range.as_ref().is_none_or(|range| range.contains(addr)))
+        .collect();
+    let addresses: HashSet<usize> = listing.iter().map(|(addr, _)| *addr).collect();
+    let labels: HashSet<usize> = listing
+        .iter()
+        .filter_map(|(_, line)| match line {
+            Line::Code(opcode) => Instruction::from(*opcode).target_address(),
+            Line::Data(_) => None,
+        })
+        .filter(|nnn| addresses.contains(nnn))
+        .collect();
+    let label = |nnn: usize| format!("L_{:04X}", nnn);
+
+    for (addr, line) in listing {
+        if labels.contains(addr) {
+            writeln!(w, "{}:", label(*addr))?;
+        }
+        let (raw, text) = match *line {
+            Line::Code(opcode) => {
+                let text = match Instruction::from(opcode) {
+                    Jmp(nnn) if labels.contains(&nnn) => format!("{:<5} {}", "JMP", label(nnn)),
+                    Call(nnn) if labels.contains(&nnn) => format!("{:<5} {}", "CALL", label(nnn)),
+                    Jmpz(nnn) if labels.contains(&nnn) => format!("{:<5} {}", "JMPZ", label(nnn)),
+                    instr => instr.to_string(),
+                };
+                (format!("{:#06X}", opcode), text)
+            }
+            Line::Data(byte) => (
+                format!("{:#04X}", byte),
+                format!("{:<5} {:#04X}", "DB", byte),
+            ),
+        };
+        writeln!(w, "{:#06X}: {:<6}  {}", addr, raw, text)?;
+    }
+    Ok(())
+}
+
+/// Every instruction that is reachable from the start of the program, which is loaded at
+/// `start`, with its address, in order of address.  Unlike the smart disassembler, this
+/// includes opcodes that aren't valid instructions, but doesn't follow them.
+pub fn reachable_instructions(rom: &[u8], start: usize) -> Vec<(usize, Instruction)> {
+    walk_code(rom, start)
+        .into_iter()
+        .enumerate()
+        .filter_map(|(offset, instr)| instr.map(|instr| (start + offset, instr)))
+        .collect()
+}
+
+/// Follow every path of execution from the start of the program, which is loaded at
+/// `start`.  Returns, for each byte of `rom`, whether an instruction starts at that byte.
+fn find_code(rom: &[u8], start: usize) -> Vec<bool> {
+    walk_code(rom, start)
+        .into_iter()
+        // Most likely data that execution can't actually reach
+        .map(|instr| instr.is_some_and(|instr| !matches!(instr, Err(_))))
+        .collect()
+}
+
+/// Follow every path of execution from the start of the program, which is loaded at
+/// `start`.  Returns, for each byte of `rom`, the instruction that starts at that byte.
+/// Paths end at opcodes that aren't valid instructions.
+fn walk_code(rom: &[u8], start: usize) -> Vec<Option<Instruction>> {
+    let mut code = vec![None; rom.len()];
+    let mut pending = vec![start];
+
+    while let Some(addr) = pending.pop() {
+        let offset = match addr.checked_sub(start) {
+            Some(offset) if offset + 1 < rom.len() && code[offset].is_none() => offset,
+            _ => continue,
+        };
+        let instr = Instruction::from(u16::from_be_bytes([rom[offset], rom[offset + 1]]));
+        code[offset] = Some(instr);
+        let next = addr + 2;
+        match instr {
+            Err(_) | Ret => {}
+            // The offset added to the target isn't known, but the target itself is
+            // usually the start of a jump table
+            Jmp(nnn) | Jmpz(nnn) => pending.push(nnn),
+            Call(nnn) => pending.extend([nnn, next]),
+            Skeb(..) | Skneb(..) | Ske(..) | Skne(..) | Skp(_) | Sknp(_) => {
+                pending.extend([next, next + 2])
+            }
+            _ => pending.push(next),
+        }
+    }
+
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disassembly of `rom`, loaded at 0x200, as text
+    fn disassembly(rom: &[u8]) -> String {
+        let mut w = Vec::new();
+        disassemble_to(rom, 0x200, 0, None, &mut w).unwrap();
+        String::from_utf8(w).unwrap()
+    }
+
+    #[test]
+    fn writes_to_any_writer() {
+        assert_eq!(
+            disassembly(&[0x00, 0xE0, 0x60, 0x0A]),
+            "0x0200: 0x00E0  CLS  \n0x0202: 0x600A  LDB   V0, 0x0A\n"
+        );
+    }
+
+    #[test]
+    fn last_byte_of_odd_length_program_is_data() {
+        assert_eq!(
+            disassembly(&[0x00, 0xE0, 0xAB]),
+            "0x0200: 0x00E0  CLS  \n0x0202: 0xAB    DB    0xAB\n"
+        );
+    }
+
+    #[test]
+    fn hexdump_shows_hex_and_ascii() {
+        let mut bytes = b"0123456789ABCDEF".to_vec();
+        bytes.extend([0x00, 0x20, 0x7E, 0xFF]);
+        let mut w = Vec::new();
+        hexdump_to(&bytes, 0x300, &mut w).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "0x0300: 30 31 32 33 34 35 36 37 38 39 41 42 43 44 45 46  |0123456789ABCDEF|\n\
+             0x0310: 00 20 7E FF                                      |. ~.|\n"
+        );
+    }
+
+    #[test]
+    fn shows_raw_bytes_and_aligns_columns() {
+        assert_eq!(
+            disassembly(&[0xA2, 0x2A, 0xD0, 0x15, 0x12, 0x00, 0xF0]),
+            "L_0200:\n\
+             0x0200: 0xA22A  LDI   0x22A\n\
+             0x0202: 0xD015  DRAW  V0, V1, 0x5\n\
+             0x0204: 0x1200  JMP   L_0200\n\
+             0x0206: 0xF0    DB    0xF0\n"
+        );
+    }
+}
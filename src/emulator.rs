@@ -1,25 +1,119 @@
-use std::time::{Duration, Instant};
+use std::{
+    cmp::{max, min},
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{self, stderr, BufReader, BufWriter, Write},
+    iter::Peekable,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
+use log::{error, info, warn};
 use sdl2::{
-    audio::{AudioCallback, AudioSpecDesired},
-    event::Event,
-    keyboard::{Keycode, Scancode},
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    controller::{Button, GameController},
+    event::{Event, WindowEvent},
+    keyboard::Scancode,
+    mouse::MouseButton,
     pixels::PixelFormatEnum,
+    render::{Texture, WindowCanvas},
+    video::FullscreenType,
+    EventPump, GameControllerSubsystem, Sdl,
+};
+
+use crate::{
+    chip8::{
+        Chip8, Quirks, DISPLAY_HEIGHT, DISPLAY_WIDTH, KEYPAD_SIZE, MEMORY_SIZE, TIMER_FREQUENCY,
+    },
+    disassembler::hexdump_to,
+    framebuffer::Rect,
+    frontend::{
+        sdl::{default_keymap, TextureDisplay},
+        Display,
+    },
+    input::{read_events, InputEvent},
+    instruction::Instruction,
+    quirks_db, quirks_doc,
+    recorder::Recorder,
+    screenshot,
+    spin::SpinDetector,
+    tracer::{Registers, Tracer},
+    watch::Watchpoint,
 };
 
-use crate::chip8::{Chip8, Quirks, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+#[cfg(feature = "overlay")]
+use crate::overlay::Overlay;
+
+mod terminal;
+
+/// Duration (in milliseconds) over which the buzzer fades in and out
+const ENVELOPE_MILLIS: i64 = 5;
+/// Brightness lost by an unset pixel each frame when ghosting is enabled
+const GHOSTING_DECAY: u8 = 64;
+/// Number of frames a pixel must stay set to fully change to the CRT gradient's color
+const GRADIENT_FRAMES: u8 = 60;
+/// Number of bytes of memory in a state dump
+const DUMP_SIZE: usize = 256;
+/// Machine cycles available to the interpreter each second on the COSMAC VIP, whose
+/// 1.76 MHz CPU runs one machine cycle every 8 clock cycles.  About a third of the time is
+/// lost to the display's DMA and interrupt routine.
+const VIP_CYCLES_PER_SECOND: u32 = 146_400;
+/// Most frames run at once to catch up with vsync, e.g., after the window was dragged
+const MAX_FRAMES_DUE: u32 = 4;
+/// Version of the JSON state dump format, incremented whenever a field is changed or removed
+const DUMP_VERSION: u32 = 1;
 
 pub struct Emulator {
     chip: Chip8,
     options: Options,
+    /// Number of times each kind of instruction was executed, by mnemonic
+    instruction_counts: HashMap<&'static str, u64>,
+    /// Addresses the program was loaded into
+    program: Range<usize>,
+    /// Addresses of the instructions already reported for modifying the program
+    self_modifying: HashSet<usize>,
+    /// Quirks in effect, also applied to the programs loaded from the playlist
+    quirks: Quirks,
+    /// Programs to switch to, in order, by title.  The running program is at the back.
+    playlist: VecDeque<(String, Vec<u8>)>,
+    /// Instructions executed per frame, which is below `options.ipf` while adaptive speed
+    /// is slowing the emulator down
+    ipf: u16,
+    /// State of the virtual machine at the end of each recent frame, oldest first
+    rewind: VecDeque<Chip8>,
+    /// Number of times each unknown opcode was executed
+    unknown_opcodes: HashMap<u16, u64>,
 }
 
 #[derive(Debug)]
 pub struct Options {
+    /// Title of the window, which is followed by the measured speed
+    pub title: String,
+    /// Programs after the first, with their titles
+    pub playlist: Vec<(String, Vec<u8>)>,
+    /// Time after which to switch to the next program
+    pub rom_duration: Option<Duration>,
+    /// Address the program is loaded at and starts running from
+    pub start: usize,
+    /// Font replacing the default font
+    pub font: Option<Vec<u8>>,
+    /// Number of addresses the stack can hold
+    pub stack_size: usize,
+    /// Bytes written over memory after the program is loaded, by address
+    pub patches: Vec<(usize, Vec<u8>)>,
+    /// Where the display is drawn and keys are read from
+    pub backend: Backend,
     // Frames per second
     pub fps: u16,
     /// Instructions executed per frame
     pub ipf: u16,
+    /// How many instructions are executed per frame
+    pub timing: Timing,
     /// Window scale factor
     pub scale: u32,
     /// Foreground color (RGBA8888)
@@ -28,20 +122,320 @@ pub struct Options {
     pub bg: u32,
     /// Pitch of the buzzer (in Hz)
     pub pitch: u16,
+    /// Shape of the buzzer's tone
+    pub waveform: Waveform,
+    /// Volume of the buzzer, from 0.0 to 1.0
+    pub volume: f32,
+    /// Don't open an audio device, so the buzzer is never heard
+    pub no_audio: bool,
     /// Limit only one draw operation per frame
     pub display_wait: bool,
+    /// Delay each draw operation until the start of the next frame
+    pub vblank_wait: bool,
+    /// Path of the GIF to record gameplay to
+    pub record: Option<PathBuf>,
+    /// Path of the file to record keypad input to
+    pub record_input: Option<PathBuf>,
+    /// Path of the file to replay keypad input from, instead of the keyboard and gamepads
+    pub play_input: Option<PathBuf>,
+    /// Number of recent frames whose state is kept for rewinding
+    pub rewind_frames: usize,
+    /// Seed of the random number generator
+    pub seed: Option<u64>,
+    /// Path of the TrueType font used by the debugging overlay
+    #[cfg(feature = "overlay")]
+    pub overlay_font: Option<PathBuf>,
+    /// Custom key bindings, from scancodes to CHIP-8 keys
+    pub keymap: HashMap<Scancode, u8>,
+    /// Custom gamepad bindings, from buttons to CHIP-8 keys
+    pub gamepad_keymap: HashMap<Button, u8>,
+    /// CHIP-8 key pressed by the left mouse button
+    pub mouse_key: Option<u8>,
+    /// Write the state of the virtual machine to stderr when the emulator exits
+    pub dump_state: bool,
+    /// Path of the PNG image to save the display to when the emulator exits
+    pub screenshot_on_exit: Option<PathBuf>,
+    /// Write the state dump as JSON to stdout, instead of text to stderr
+    pub json: bool,
+    /// Log every executed instruction
+    pub trace: bool,
+    /// Path of the file to log executed instructions to, instead of stderr
+    pub trace_to: Option<PathBuf>,
+    /// Path of the CSV file to log the address and opcode of each instruction executed by
+    /// `run_headless` to
+    pub step_log: Option<PathBuf>,
+    /// Largest number of executed instructions to log
+    pub trace_limit: Option<u64>,
+    /// Count how many times each kind of instruction is executed, and write the counts to
+    /// stderr when the emulator exits
+    pub profile_instructions: bool,
+    /// Log every draw and clear screen instruction, with the frame it was executed in
+    pub log_draws: bool,
+    /// Write the hash of the display to stdout after every this many frames
+    pub frame_hash_every: Option<u64>,
+    /// Pause when the program halts by jumping to itself
+    pub halt_on_spin: bool,
+    /// Report calls to machine code routines, which are otherwise ignored
+    pub trap_sys: Option<SysTrap>,
+    /// Pause when the value of any of these locations changes
+    pub watchpoints: Vec<Watchpoint>,
+    /// Warn when the program writes to the memory it was loaded into
+    pub warn_self_modify: bool,
+    /// Warn the first time each unknown opcode is executed, and report how many times each
+    /// one was executed on exit
+    pub warn_unknown: bool,
+    /// Ignore the clear screen instruction, and report how many were ignored on exit
+    pub no_clear: bool,
+    /// Fade unset pixels out over a few frames, instead of instantly, to reduce flicker
+    pub ghosting: bool,
+    /// Color (RGBA8888) that set pixels change to the longer they stay set
+    pub crt_gradient: Option<u32>,
+    /// Present frames in sync with the display's refresh rate, instead of `fps`.  The
+    /// program still runs at `fps` frames per second.
+    pub vsync: bool,
+    /// Lower the instructions executed per frame while frames take longer than `fps` allows
+    pub adaptive_speed: bool,
+    /// Pause while the window doesn't have the keyboard focus
+    pub pause_on_blur: bool,
+    /// Only scale the display by whole numbers when the window is resized, leaving black
+    /// borders around it
+    pub integer_scale: bool,
+    /// Quirks to switch to and from while running, for comparison
+    pub quirks_b: Option<Quirks>,
 }
 
 impl Emulator {
-    pub fn new(rom: &[u8], options: Options, quirks: Quirks) -> Result<Self, String> {
-        let chip = Chip8::new(rom, quirks)?;
-        Ok(Self { chip, options })
+    pub fn new(rom: &[u8], mut options: Options, quirks: Quirks) -> Result<Self, String> {
+        let chip = Self::load(rom, &options, quirks)?;
+        let mut playlist: VecDeque<_> = options.playlist.drain(..).collect();
+        if !playlist.is_empty() {
+            playlist.push_back((options.title.clone(), rom.to_vec()));
+        }
+        Ok(Self {
+            chip,
+            program: options.start..options.start + rom.len(),
+            ipf: options.ipf,
+            options,
+            instruction_counts: HashMap::new(),
+            self_modifying: HashSet::new(),
+            quirks,
+            playlist,
+            rewind: VecDeque::new(),
+            unknown_opcodes: HashMap::new(),
+        })
+    }
+
+    /// Load `rom` into a new virtual machine, set up as given by `options`
+    fn load(rom: &[u8], options: &Options, quirks: Quirks) -> Result<Chip8, String> {
+        let mut chip = Chip8::new(rom, quirks, options.start)?;
+        if let Some(font) = &options.font {
+            chip.set_font(font)?;
+        }
+        chip.set_stack_size(options.stack_size);
+        for (addr, bytes) in &options.patches {
+            chip.patch(*addr, bytes)?;
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            info!("Patched {:#06X}: {}", addr, hex.join(" "));
+        }
+        if let Some(seed) = options.seed {
+            chip.set_seed(seed);
+        }
+        if options.warn_self_modify {
+            chip.log_writes();
+        }
+        if options.no_clear {
+            chip.ignore_clears();
+        }
+        Ok(chip)
+    }
+
+    /// Replace the running program with the next program in the playlist, starting over
+    /// with a new virtual machine
+    fn next_program(&mut self) -> Result<(), String> {
+        let Some((title, rom)) = self.playlist.pop_front() else {
+            return Ok(());
+        };
+        self.chip = Self::load(&rom, &self.options, self.quirks)?;
+        // The new, blank display still has to be drawn
+        self.chip.fb.updated = true;
+        self.program = self.options.start..self.options.start + rom.len();
+        self.self_modifying.clear();
+        self.rewind.clear();
+        self.options.title = title.clone();
+        self.playlist.push_back((title, rom));
+        Ok(())
     }
 
     pub fn run(&mut self) -> Result<(), String> {
+        let result = match self.options.backend {
+            Backend::Sdl => self.run_loop(),
+            Backend::Terminal => self.run_terminal(),
+        };
+        self.report_on_exit();
+        result
+    }
+
+    /// Execute `cycles` instructions as fast as possible, without opening a window or
+    /// playing audio.  The timers follow the time the instructions would have taken at the
+    /// target speed.  Returns the time spent executing instructions.
+    pub fn run_headless(&mut self, cycles: u64) -> Result<Duration, String> {
+        let mut step_log = match &self.options.step_log {
+            Some(path) => Some(BufWriter::new(
+                File::create(path).map_err(|e| format!("\'{}\': {}", path.display(), e))?,
+            )),
+            None => None,
+        };
+        let start = Instant::now();
+        let result = self.step_headless(cycles, step_log.as_mut());
+        let elapsed = start.elapsed();
+        self.report_on_exit();
+        // The final state is logged even if an instruction failed, since that's where the
+        // log ends
+        if let (Some(mut step_log), Some(path)) = (step_log, &self.options.step_log) {
+            self.finish_step_log(&mut step_log)
+                .map_err(|e| format!("\'{}\': {}", path.display(), e))?;
+        }
+        result.map(|()| elapsed)
+    }
+
+    fn step_headless(
+        &mut self,
+        cycles: u64,
+        mut step_log: Option<&mut BufWriter<File>>,
+    ) -> Result<(), String> {
+        let path = self.options.step_log.clone().unwrap_or_default();
+        let log_error = |e: io::Error| format!("\'{}\': {}", path.display(), e);
+        if let Some(step_log) = &mut step_log {
+            writeln!(step_log, "pc,opcode").map_err(log_error)?;
+        }
+        // There is no wall clock to follow, so time is simulated instead: a frame passes
+        // once a full batch of instructions has executed (or a draw operation waits for the
+        // next frame, with --display-wait or --vblank-wait), and the timers tick at 60 Hz of
+        // simulated time, exactly as many times per second as in a window
+        let frame_period = Duration::from_secs(1) / u32::from(self.options.fps);
+        let timer_period = Duration::from_secs(1) / TIMER_FREQUENCY;
+        let frame_length = match self.options.timing {
+            Timing::Ipf => u32::from(self.ipf),
+            Timing::Vip => self.cycle_budget(),
+        };
+        let mut timer_accumulator = Duration::ZERO;
+        let mut frame: u64 = 0;
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let (executed, finished) =
+                self.step_headless_frame(remaining, frame_length, step_log.as_deref_mut())?;
+            remaining -= executed;
+            if finished {
+                frame += 1;
+                self.print_frame_hash(frame);
+                timer_accumulator += frame_period;
+                while timer_accumulator >= timer_period {
+                    self.chip.tick_timers();
+                    timer_accumulator -= timer_period;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute at most `cycles` instructions of a single frame, which ends after
+    /// `frame_length` instructions (or machine cycles, with VIP timing) or at a draw operation
+    /// deferred to the next frame.  Returns the number of instructions executed, and whether
+    /// the frame ended.
+    fn step_headless_frame(
+        &mut self,
+        cycles: u64,
+        frame_length: u32,
+        mut step_log: Option<&mut BufWriter<File>>,
+    ) -> Result<(u64, bool), String> {
+        let path = self.options.step_log.clone().unwrap_or_default();
+        let log_error = |e: io::Error| format!("\'{}\': {}", path.display(), e);
+        let mut executed = 0;
+        let mut batch = 0;
+        // Whether a draw operation has modified the screen during the frame
+        let mut drawn = false;
+        loop {
+            // A deferred draw ends the frame even when no cycles are left, since nothing else
+            // could run before the next frame
+            if self.draw_deferred(executed == 0, drawn) {
+                return Ok((executed, true));
+            }
+            if executed == cycles {
+                return Ok((executed, false));
+            }
+            let pc = self.chip.pc();
+            if let (Some(step_log), Some(&[high, low])) =
+                (&mut step_log, self.chip.mem().get(pc..pc + 2))
+            {
+                let opcode = u16::from_be_bytes([high, low]);
+                writeln!(step_log, "{:#06X},{:#06X}", pc, opcode).map_err(log_error)?;
+            }
+            let instr = self.chip.step()?;
+            executed += 1;
+            drawn |= matches!(instr, Instruction::Draw(..)) && self.chip.fb.updated;
+            // Nothing is redrawn without a window
+            self.chip.fb.updated = false;
+            self.trap_sys(pc, instr)?;
+            self.warn_self_modify();
+            self.count_unknown(pc, instr);
+            if self.options.profile_instructions {
+                *self.instruction_counts.entry(instr.mnemonic()).or_default() += 1;
+            }
+
+            batch += match self.options.timing {
+                Timing::Ipf => 1,
+                Timing::Vip => cycle_cost(&instr),
+            };
+            if batch >= frame_length {
+                return Ok((executed, true));
+            }
+        }
+    }
+
+    /// End the step log with the final state of the registers, as a comment
+    fn finish_step_log(&self, step_log: &mut BufWriter<File>) -> io::Result<()> {
+        let chip = &self.chip;
+        let v: String = chip.v().iter().map(|v| format!("{:02X}", v)).collect();
+        writeln!(
+            step_log,
+            "# pc={:#06X} i={:#06X} sp={} dt={} st={} v={}",
+            chip.pc(),
+            chip.i(),
+            chip.sp(),
+            chip.dt,
+            chip.st,
+            v
+        )?;
+        step_log.flush()
+    }
+
+    /// Write the state, instruction counts, and number of ignored CLS instructions to stderr,
+    /// and save a screenshot, if requested
+    fn report_on_exit(&self) {
+        if self.options.dump_state && self.options.json {
+            self.dump_state_json();
+        } else if self.options.dump_state {
+            self.dump_state();
+        }
+        if self.options.profile_instructions {
+            self.dump_instruction_counts();
+        }
+        if let Some(count) = self.chip.ignored_clears() {
+            eprintln!("Ignored {} CLS instructions", count);
+        }
+        if self.options.warn_unknown && !self.unknown_opcodes.is_empty() {
+            self.dump_unknown_opcodes();
+        }
+        if let Some(path) = &self.options.screenshot_on_exit {
+            let fg = self.options.fg.to_be_bytes();
+            let bg = self.options.bg.to_be_bytes();
+            self.save_screenshot(path, &fg, &bg);
+        }
+    }
+
+    fn run_loop(&mut self) -> Result<(), String> {
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
-        let audio_subsystem = sdl_context.audio()?;
 
         // Required to avoid excessive conversions
         const HEIGHT: u32 = DISPLAY_HEIGHT as u32;
@@ -50,7 +444,7 @@ impl Emulator {
         // Initialize the window
         let window = video_subsystem
             .window(
-                "CHIP-8 Emulator",
+                &self.options.title,
                 WIDTH * self.options.scale,
                 HEIGHT * self.options.scale,
             )
@@ -59,157 +453,1593 @@ impl Emulator {
             .build()
             .map_err(|e| e.to_string())?;
 
-        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let mut canvas_builder = window.into_canvas();
+        if self.options.vsync {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let mut canvas = canvas_builder.build().map_err(|e| e.to_string())?;
         let texture_creator = canvas.texture_creator();
         canvas
             .set_logical_size(WIDTH, HEIGHT)
             .map_err(|e| e.to_string())?;
+        canvas.set_integer_scale(self.options.integer_scale)?;
         let mut texture = texture_creator
             .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH, HEIGHT)
             .map_err(|e| e.to_string())?;
 
-        // Initialize the audio
-        let desired_audio_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1),
-            samples: None,
+        // Initialize the debugging overlay
+        #[cfg(feature = "overlay")]
+        let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
+        #[cfg(feature = "overlay")]
+        let overlay = match &self.options.overlay_font {
+            Some(path) => Some(Overlay::load(&ttf_context, path)?),
+            None => None,
+        };
+
+        // The buzzer plays continuously, and is silent unless the gate is open
+        let gate = Arc::new(AtomicBool::new(false));
+        let audio_device = if self.options.no_audio {
+            None
+        } else {
+            // Sound isn't essential, so keep running without it
+            self.open_audio(&sdl_context, &gate)
+                .map_err(|e| warn!("audio device could not be opened: {}", e))
+                .ok()
         };
-        let audio_device = audio_subsystem.open_playback(None, &desired_audio_spec, |spec| {
-            let freq = if spec.freq < 0 {
-                i64::from(-spec.freq)
-            } else {
-                i64::from(spec.freq)
-            };
-            let pitch = i64::from(self.options.pitch);
-            SquareWave {
-                channels: usize::from(spec.channels),
-                half_period: freq / (2 * pitch),
-                volume: 0.25,
-                index: 0,
-            }
-        })?;
 
         // Colors as RGBA values
         let fg = self.options.fg.to_be_bytes();
         let bg = self.options.bg.to_be_bytes();
+        let aged = self.options.crt_gradient.map(u32::to_be_bytes);
+
+        let recorder = match &self.options.record {
+            Some(path) => Some(
+                Recorder::create(path, self.options.scale, self.options.fps, &fg, &bg)
+                    .map_err(|e| format!("\'{}\': {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+
+        let mut tracer = if self.options.trace {
+            let path = self.options.trace_to.as_deref();
+            Some(
+                Tracer::create(path, self.options.trace_limit).map_err(|e| match path {
+                    Some(path) => format!("\'{}\': {}", path.display(), e),
+                    None => e,
+                })?,
+            )
+        } else {
+            None
+        };
+
+        let mut input_log = match &self.options.record_input {
+            Some(path) => Some(BufWriter::new(
+                File::create(path).map_err(|e| format!("\'{}\': {}", path.display(), e))?,
+            )),
+            None => None,
+        };
+        let mut playback = match &self.options.play_input {
+            Some(path) => {
+                let events = File::open(path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|file| read_events(BufReader::new(file)))
+                    .map_err(|e| format!("\'{}\': {}", path.display(), e))?;
+                Some(events.into_iter().peekable())
+            }
+            None => None,
+        };
+        let mut state = LoopState::new(input_log.is_some() || playback.is_some());
+
+        // Gamepads are opened as they are connected, including those connected at startup
+        let mut devices = Devices {
+            canvas,
+            audio_device,
+            recorder,
+            controller_subsystem: sdl_context.game_controller()?,
+            controllers: HashMap::new(),
+        };
 
         let mut event_pump = sdl_context.event_pump()?;
-        let nanos_per_frame: u128 =
-            Duration::from_secs(1).as_nanos() / u128::from(self.options.fps);
+        let frame_period = self.frame_period();
+        // Time that has passed, but not yet been emulated, when presentation is driven by vsync
+        let mut frame_accumulator = Duration::ZERO;
 
         'running: loop {
             let start = Instant::now();
-            for _ in 0..self.options.ipf {
-                for event in event_pump.poll_iter() {
-                    match event {
-                        Event::Quit { .. }
-                        | Event::KeyDown {
-                            keycode: Some(Keycode::Escape),
-                            ..
-                        } => break 'running,
-                        Event::KeyDown {
-                            scancode: Some(scancode),
-                            ..
-                        } => {
-                            if let Some(key) = self.keymap(scancode) {
-                                self.chip.keypad.key_pressed(key);
+            // Events are also polled between instructions, but no instructions run while no
+            // frame is due
+            self.poll_events(&mut event_pump, &mut state, &mut devices)?;
+            if state.quit {
+                break;
+            }
+            // With vsync, presenting waits for the display to refresh, so run however many
+            // frames are due at the target frame rate instead of exactly one.  After a stall,
+            // only a few frames are caught up on, instead of running the program fast for a
+            // while.
+            let frames_due = if self.options.vsync {
+                frame_accumulator = frame_accumulator.min(frame_period * MAX_FRAMES_DUE);
+                let frames_due = (frame_accumulator.as_nanos() / frame_period.as_nanos()) as u32;
+                frame_accumulator -= frame_period * frames_due;
+                frames_due
+            } else {
+                1
+            };
+            for _ in 0..frames_due {
+                loop {
+                    // Poll before deciding whether the batch runs, so that a frame advance
+                    // (N) also applies the recorded input of the frame it advances
+                    self.poll_events(&mut event_pump, &mut state, &mut devices)?;
+                    if state.quit {
+                        break 'running;
+                    }
+                    // The frame count only advances once the frame is done, so that
+                    // everything in the batch is reported with the same frame number
+                    let running = !state.paused;
+                    if state.deterministic && running {
+                        self.apply_recorded_input(
+                            state.frame,
+                            &mut state.keypad_events,
+                            &mut input_log,
+                            &mut playback,
+                        )?;
+                        state.timer_accumulator += frame_period;
+                    }
+                    // Machine cycles spent by the batch, with VIP timing
+                    let mut cycles = 0;
+                    // Whether a draw operation has modified the screen during the batch
+                    let mut drawn = false;
+                    for n in 0..self.batch_size() {
+                        if n > 0 {
+                            self.poll_events(&mut event_pump, &mut state, &mut devices)?;
+                            if state.quit {
+                                break 'running;
                             }
                         }
-                        Event::KeyUp {
-                            scancode: Some(scancode),
-                            ..
-                        } => {
-                            if let Some(key) = self.keymap(scancode) {
-                                self.chip.keypad.key_released(key);
+                        // Nothing can happen until new events arrive, so skip the rest of the
+                        // frame
+                        if (state.paused && !state.single_step)
+                            || state.rewinding
+                            || self.chip.waiting_for_key()
+                        {
+                            break;
+                        }
+                        if self.draw_deferred(n == 0, drawn) {
+                            break;
+                        }
+                        let instr = self.step_debugged(&mut state, tracer.as_mut())?;
+                        drawn |= matches!(instr, Instruction::Draw(..)) && self.chip.fb.updated;
+                        self.update_texture(fg, bg, &mut texture)?;
+                        if self.options.timing == Timing::Vip {
+                            cycles += cycle_cost(&instr);
+                            if cycles >= self.cycle_budget() {
+                                break;
                             }
                         }
-                        _ => {}
                     }
-                }
-                self.chip.step();
+                    if running && state.rewinding {
+                        self.rewind_frame(&fg, &bg, &mut texture)?;
+                    } else if running {
+                        state.frame += 1;
+                        self.print_frame_hash(state.frame);
+                        self.save_rewind_state();
+                    }
 
-                if self.chip.st > 0 {
-                    audio_device.resume();
-                } else {
-                    audio_device.pause();
-                }
-                if self.chip.fb.updated {
-                    let pixels = self.chip.fb.to_color_model(&fg, &bg);
-                    texture.with_lock(None, |buffer: &mut [u8], _: usize| {
-                        buffer.copy_from_slice(&pixels);
-                    })?;
-                    self.chip.fb.updated = false;
-                    if self.options.display_wait {
+                    // In turbo mode, keep executing batches for the rest of the frame instead of
+                    // sleeping
+                    if state.paused
+                        || state.frame_advance
+                        || !state.turbo
+                        || start.elapsed() >= frame_period
+                    {
                         break;
                     }
                 }
+                self.end_frame(&mut state, &mut devices, &gate)?;
             }
+
+            if self.redraw_every_frame() {
+                let fb = &self.chip.fb;
+                texture.with_lock(None, |buffer: &mut [u8], _: usize| match &aged {
+                    Some(aged) => {
+                        fb.write_aged_color_model(&fg, aged, &bg, GRADIENT_FRAMES, buffer)
+                    }
+                    None => fb.write_blended_color_model(&fg, &bg, buffer),
+                })?;
+            }
+            let canvas = &mut devices.canvas;
             canvas.clear();
             canvas.copy(&texture, None, None)?;
+            #[cfg(feature = "overlay")]
+            if let Some(overlay) = overlay.as_ref().filter(|_| state.show_overlay) {
+                overlay.draw(canvas, &texture_creator, &self.chip)?;
+            }
             canvas.present();
+            self.update_title(&mut state, canvas)?;
+            self.advance_playlist(&mut state, canvas)?;
+
+            if !state.paused && !state.turbo {
+                self.adapt_speed(start.elapsed(), frame_period);
+            }
+            // Sleep until the next frame is due.  With vsync, presenting usually waits for the
+            // display to refresh, but not always (e.g., while the window is hidden).
+            let next_frame = frame_period.saturating_sub(frame_accumulator);
+            if let (Some(remaining), false) = (next_frame.checked_sub(start.elapsed()), state.turbo)
+            {
+                std::thread::sleep(remaining);
+            }
+
+            // The timers are part of the rewound state, so they stop while rewinding
+            if !state.paused && !state.rewinding && !state.deterministic {
+                state.timer_accumulator += start.elapsed();
+            }
+            if self.options.vsync {
+                frame_accumulator += start.elapsed();
+            }
+            let timer_period = Duration::from_secs(1) / TIMER_FREQUENCY;
+            while state.timer_accumulator >= timer_period {
+                self.chip.tick_timers();
+                state.timer_accumulator -= timer_period;
+            }
+        }
+
+        if let Some(recorder) = devices.recorder {
+            recorder.finish()?;
+        }
+        if let Some(tracer) = tracer {
+            tracer.finish()?;
+        }
+        if let Some(mut input_log) = input_log {
+            input_log.flush().map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Handle the events received since the last poll.  Keypad input is applied right away,
+    /// unless it's only applied at the start of each frame.
+    fn poll_events(
+        &mut self,
+        event_pump: &mut EventPump,
+        state: &mut LoopState,
+        devices: &mut Devices,
+    ) -> Result<(), String> {
+        for event in event_pump.poll_iter() {
+            self.handle_event(event, state, devices)?;
+        }
+        if !state.deterministic {
+            for (key, pressed) in state.keypad_events.drain(..) {
+                self.set_key(key, pressed);
+            }
+        }
+        Ok(())
+    }
+
+    /// Act on a hotkey, a change of focus, a gamepad being connected or disconnected, or
+    /// input for the keypad, which is queued in `state`
+    fn handle_event(
+        &mut self,
+        event: Event,
+        state: &mut LoopState,
+        devices: &mut Devices,
+    ) -> Result<(), String> {
+        match event {
+            Event::Quit { .. } => state.quit = true,
+            Event::KeyDown {
+                scancode: Some(scancode),
+                repeat,
+                ..
+            } => match Hotkey::of(scancode) {
+                Some(hotkey) if repeat && !hotkey.repeats() => {}
+                Some(hotkey) => self.press_hotkey(hotkey, state, devices)?,
+                None => {
+                    if let Some(key) = self.keymap(scancode) {
+                        state.keypad_events.push((key, true));
+                    }
+                }
+            },
+            Event::KeyUp {
+                scancode: Some(scancode),
+                ..
+            } => match Hotkey::of(scancode) {
+                Some(Hotkey::Turbo) => state.turbo = false,
+                Some(Hotkey::Rewind) => state.rewinding = false,
+                Some(_) => {}
+                None => {
+                    if let Some(key) = self.keymap(scancode) {
+                        state.keypad_events.push((key, false));
+                    }
+                }
+            },
+            Event::Window {
+                win_event: WindowEvent::FocusLost,
+                ..
+            } if self.options.pause_on_blur && !state.paused => {
+                state.paused = true;
+                state.blurred = true;
+            }
+            Event::Window {
+                win_event: WindowEvent::FocusGained,
+                ..
+            } if state.blurred => {
+                state.paused = false;
+                state.blurred = false;
+            }
+            Event::MouseButtonDown {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                if let Some(key) = self.options.mouse_key {
+                    state.keypad_events.push((key, true));
+                }
+            }
+            Event::MouseButtonUp {
+                mouse_btn: MouseButton::Left,
+                ..
+            } => {
+                if let Some(key) = self.options.mouse_key {
+                    state.keypad_events.push((key, false));
+                }
+            }
+            Event::ControllerDeviceAdded { which, .. } => {
+                match devices.controller_subsystem.open(which) {
+                    Ok(controller) => {
+                        devices
+                            .controllers
+                            .insert(controller.instance_id(), controller);
+                    }
+                    Err(e) => warn!("gamepad could not be opened: {}", e),
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                devices.controllers.remove(&which);
+            }
+            Event::ControllerButtonDown { button, .. } => {
+                if let Some(key) = self.gamepad_keymap(button) {
+                    state.keypad_events.push((key, true));
+                }
+            }
+            Event::ControllerButtonUp { button, .. } => {
+                if let Some(key) = self.gamepad_keymap(button) {
+                    state.keypad_events.push((key, false));
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Act on a hotkey being pressed.  The debugger's hotkeys only work while paused.
+    fn press_hotkey(
+        &mut self,
+        hotkey: Hotkey,
+        state: &mut LoopState,
+        devices: &mut Devices,
+    ) -> Result<(), String> {
+        match hotkey {
+            Hotkey::Quit => state.quit = true,
+            Hotkey::Turbo => state.turbo = true,
+            Hotkey::Rewind => state.rewinding = self.options.rewind_frames > 0,
+            Hotkey::Pause => {
+                state.paused = !state.paused;
+                state.blurred = false;
+                state.break_below = None;
+                if state.paused {
+                    self.report_position();
+                }
+            }
+            Hotkey::SwapQuirks => self.swap_quirks(),
+            Hotkey::Step if state.paused => state.single_step = true,
+            Hotkey::FrameAdvance if state.paused => {
+                state.paused = false;
+                state.frame_advance = true;
+            }
+            Hotkey::DumpMemory if state.paused => self.dump_memory(),
+            Hotkey::Backtrace if state.paused => self.print_backtrace(),
+            Hotkey::StepOver if state.paused => {
+                // Run the whole subroutine, until it returns
+                if let Instruction::Call(_) = self.chip.next_instruction() {
+                    state.break_below = Some(self.chip.sp() + 1);
+                    state.paused = false;
+                } else {
+                    state.single_step = true;
+                }
+            }
+            Hotkey::StepOut if state.paused && self.chip.sp() > 0 => {
+                state.break_below = Some(self.chip.sp());
+                state.paused = false;
+            }
+            Hotkey::Step
+            | Hotkey::FrameAdvance
+            | Hotkey::DumpMemory
+            | Hotkey::Backtrace
+            | Hotkey::StepOver
+            | Hotkey::StepOut => {}
+            Hotkey::Mute => state.toggle_mute(devices.audio_device.as_mut(), self.options.volume),
+            Hotkey::Fullscreen => toggle_fullscreen(
+                &mut devices.canvas,
+                self.options.scale,
+                self.options.integer_scale,
+            )?,
+            Hotkey::Screenshot => {
+                let fg = self.options.fg.to_be_bytes();
+                let bg = self.options.bg.to_be_bytes();
+                self.save_screenshot(&screenshot::timestamped_path(), &fg, &bg);
+            }
+            Hotkey::NextProgram => state.skip_program = true,
+            #[cfg(feature = "overlay")]
+            Hotkey::Overlay => {
+                if self.options.overlay_font.is_some() {
+                    state.show_overlay = !state.show_overlay;
+                } else {
+                    info!("Use --overlay-font to enable the overlay");
+                }
+            }
+            #[cfg(not(feature = "overlay"))]
+            Hotkey::Overlay => info!("The overlay requires the overlay feature"),
+            Hotkey::Record => {
+                if let Some(recorder) = &mut devices.recorder {
+                    recorder.active = !recorder.active;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute the next instruction, with the debugging features that watch every
+    /// instruction: tracing, profiling, watchpoints, and stepping, which may pause
+    fn step_debugged(
+        &mut self,
+        state: &mut LoopState,
+        tracer: Option<&mut Tracer>,
+    ) -> Result<Instruction, String> {
+        let pc = self.chip.pc();
+        let before = Registers::of(&self.chip);
+        let watched: Vec<u8> = self
+            .options
+            .watchpoints
+            .iter()
+            .map(|watch| watch.value(&self.chip))
+            .collect();
+        self.log_draw(state.frame);
+        let instr = self.chip.step()?;
+        state.stats_steps += 1;
+        self.warn_self_modify();
+        self.count_unknown(pc, instr);
+        state.spin.executed(pc);
+        if self.trap_sys(pc, instr)? {
+            state.paused = true;
+            state.single_step = true;
+        }
+        if let Some(tracer) = tracer {
+            tracer.trace(pc, &instr, &before, &self.chip)?;
+        }
+        if self.options.profile_instructions {
+            *self.instruction_counts.entry(instr.mnemonic()).or_default() += 1;
+        }
+        for (watch, &old) in self.options.watchpoints.iter().zip(&watched) {
+            let new = watch.value(&self.chip);
+            if new != old {
+                eprintln!(
+                    "{:#06X}: {} changed {} from {:#04X} to {:#04X}",
+                    pc, instr, watch, old, new
+                );
+                state.paused = true;
+                state.single_step = true;
+            }
+        }
+        if state.single_step {
+            state.single_step = false;
+            self.report_position();
+        }
+        if state.break_below.is_some_and(|sp| self.chip.sp() < sp) {
+            state.break_below = None;
+            state.paused = true;
+            self.report_position();
+        }
+        Ok(instr)
+    }
+
+    /// Copy the pixels changed since the last call to `texture`, unless the whole texture is
+    /// redrawn every frame anyway
+    fn update_texture(
+        &mut self,
+        fg: [u8; 4],
+        bg: [u8; 4],
+        texture: &mut Texture,
+    ) -> Result<(), String> {
+        if !self.chip.fb.updated {
+            return Ok(());
+        }
+        if !self.redraw_every_frame() {
+            TextureDisplay::new(texture, fg, bg).draw(&self.chip.fb)?;
+        }
+        self.chip.fb.clear_dirty();
+        self.chip.fb.updated = false;
+        Ok(())
+    }
+
+    /// Finish a frame: start or stop the buzzer, fade and age the pixels, record the frame,
+    /// report a program stuck in a loop, and pause again after advancing a single frame
+    fn end_frame(
+        &mut self,
+        state: &mut LoopState,
+        devices: &mut Devices,
+        gate: &AtomicBool,
+    ) -> Result<(), String> {
+        // The sound timer only changes at 60 Hz, so the buzzer is only started or stopped
+        // once per frame
+        gate.store(
+            self.chip.st > 0 && !state.paused && !state.rewinding,
+            Ordering::Relaxed,
+        );
+        if self.options.ghosting {
+            self.chip.fb.decay_intensity(GHOSTING_DECAY);
+        }
+        if self.options.crt_gradient.is_some() && !state.paused {
+            self.chip.fb.age_pixels();
+        }
+        if let (Some(recorder), false) = (&mut devices.recorder, state.paused) {
+            recorder.record(&self.chip.fb)?;
+        }
+        // A loop this tight is either stuck for good or waiting for a key
+        if let Some(pc) = state.spin.end_frame() {
+            warn!("{:#06X}: program halted in an infinite loop", pc);
+            if self.options.halt_on_spin {
+                state.paused = true;
+            }
+        }
+        if state.frame_advance {
+            state.frame_advance = false;
+            state.paused = true;
+            // The timers advance by exactly one frame, no matter how long it took
+            state.timer_accumulator += self.frame_period();
+            self.report_position();
+        }
+        Ok(())
+    }
+
+    /// Show the measured speed in the window title.  The speed is measured over a second, so
+    /// the title is only updated once per second.
+    fn update_title(&self, state: &mut LoopState, canvas: &mut WindowCanvas) -> Result<(), String> {
+        state.stats_frames += 1;
+        let elapsed = state.stats_start.elapsed();
+        if elapsed < Duration::from_secs(1) {
+            return Ok(());
+        }
+        let seconds = elapsed.as_secs_f64();
+        let mut title = format!(
+            "{} - {:.0} FPS, {:.0} IPS",
+            self.options.title,
+            f64::from(state.stats_frames) / seconds,
+            state.stats_steps as f64 / seconds
+        );
+        if self.options.adaptive_speed {
+            title.push_str(&format!(", {} IPF", self.ipf));
+        }
+        canvas
+            .window_mut()
+            .set_title(&title)
+            .map_err(|e| e.to_string())?;
+        state.stats_start = Instant::now();
+        state.stats_frames = 0;
+        state.stats_steps = 0;
+        Ok(())
+    }
+
+    /// Switch to the next program in the playlist if asked to, or if the running program's
+    /// time is up
+    fn advance_playlist(
+        &mut self,
+        state: &mut LoopState,
+        canvas: &mut WindowCanvas,
+    ) -> Result<(), String> {
+        let expired = self
+            .options
+            .rom_duration
+            .is_some_and(|duration| state.program_start.elapsed() >= duration);
+        if (state.skip_program || expired) && !self.playlist.is_empty() {
+            self.next_program()?;
+            canvas
+                .window_mut()
+                .set_title(&self.options.title)
+                .map_err(|e| e.to_string())?;
+            state.program_start = Instant::now();
+            state.break_below = None;
+            state.spin.reset();
+        }
+        state.skip_program = false;
+        Ok(())
+    }
+
+    /// Open the audio device and start the buzzer, which sounds while `gate` is open
+    fn open_audio(
+        &self,
+        sdl_context: &Sdl,
+        gate: &Arc<AtomicBool>,
+    ) -> Result<AudioDevice<Buzzer>, String> {
+        let audio_subsystem = sdl_context.audio()?;
+        let desired_audio_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let audio_device = audio_subsystem.open_playback(None, &desired_audio_spec, |spec| {
+            let freq = if spec.freq < 0 {
+                i64::from(-spec.freq)
+            } else {
+                i64::from(spec.freq)
+            };
+            Buzzer {
+                channels: usize::from(spec.channels),
+                volume: self.options.volume,
+                phase: 0.0,
+                phase_step: f32::from(self.options.pitch) / freq as f32,
+                waveform: self.options.waveform,
+                gate: Arc::clone(gate),
+                envelope: 0.0,
+                envelope_step: 1000.0 / (freq * ENVELOPE_MILLIS) as f32,
+            }
+        })?;
+        audio_device.resume();
+        Ok(audio_device)
+    }
 
-            if self.chip.st > 0 {
-                self.chip.st -= 1;
+    /// Apply the keypad input of the start of `frame`: the replayed events when replaying,
+    /// or otherwise the events received since the last frame, which are also recorded
+    fn apply_recorded_input(
+        &mut self,
+        frame: u64,
+        keypad_events: &mut Vec<(u8, bool)>,
+        input_log: &mut Option<BufWriter<File>>,
+        playback: &mut Option<Peekable<std::vec::IntoIter<InputEvent>>>,
+    ) -> Result<(), String> {
+        if let Some(playback) = playback {
+            // The keyboard and gamepads are ignored
+            keypad_events.clear();
+            while let Some(event) = playback.next_if(|event| event.frame <= frame) {
+                self.set_key(event.key, event.pressed);
             }
-            if self.chip.dt > 0 {
-                self.chip.dt -= 1;
+            return Ok(());
+        }
+        for (key, pressed) in keypad_events.drain(..) {
+            self.set_key(key, pressed);
+            if let Some(input_log) = input_log {
+                let event = InputEvent {
+                    frame,
+                    key,
+                    pressed,
+                };
+                writeln!(input_log, "{}", event).map_err(|e| e.to_string())?;
             }
+        }
+        Ok(())
+    }
+
+    /// Report `instr`, executed at `pc`, if it called a machine code routine and calls are
+    /// trapped.  Returns true if the emulator should pause.
+    fn trap_sys(&self, pc: usize, instr: Instruction) -> Result<bool, String> {
+        let (Instruction::Sys(nnn), Some(trap)) = (instr, self.options.trap_sys) else {
+            return Ok(false);
+        };
+        if trap == SysTrap::Error {
+            return Err(format!(
+                "{:#06X}: machine code routine at {:#05X} cannot be executed",
+                pc, nnn
+            ));
+        }
+        warn!(
+            "{:#06X}: {} called a machine code routine, which was ignored",
+            pc, instr
+        );
+        Ok(trap == SysTrap::Pause)
+    }
+
+    /// Press or release a key on the keypad
+    fn set_key(&mut self, key: u8, pressed: bool) {
+        if pressed {
+            self.chip.keypad.key_pressed(key);
+        } else {
+            self.chip.keypad.key_released(key);
+        }
+    }
+
+    /// Time each frame takes at the target frame rate
+    fn frame_period(&self) -> Duration {
+        Duration::from_secs(1) / u32::from(self.options.fps)
+    }
+
+    /// Whether colors depend on more than whether pixels are set, so the whole texture is
+    /// redrawn once per frame instead of as pixels change
+    fn redraw_every_frame(&self) -> bool {
+        self.options.ghosting || self.options.crt_gradient.is_some()
+    }
+
+    /// Most instructions executed per frame.  With VIP timing, the batch usually ends
+    /// earlier, once its cycle budget is spent.
+    fn batch_size(&self) -> u16 {
+        match self.options.timing {
+            Timing::Ipf => self.ipf,
+            Timing::Vip => u16::MAX,
+        }
+    }
+
+    /// With adaptive speed, execute fewer instructions per frame when the last frame took
+    /// `elapsed`, which is longer than `frame_period`, and more again, up to `options.ipf`,
+    /// once frames are quick
+    fn adapt_speed(&mut self, elapsed: Duration, frame_period: Duration) {
+        if !self.options.adaptive_speed {
+            return;
+        }
+        // Slow down quickly, but speed up gradually, so the speed doesn't oscillate
+        if elapsed > frame_period {
+            self.ipf = self.ipf.saturating_sub(max(self.ipf / 8, 1)).max(1);
+        } else if elapsed < frame_period * 3 / 4 {
+            self.ipf = min(
+                self.ipf.saturating_add(max(self.ipf / 64, 1)),
+                self.options.ipf,
+            );
+        }
+    }
+
+    /// Switch between the quirks the emulator was started with and `options.quirks_b`, if
+    /// given.  The quirks also apply to programs loaded afterwards.
+    fn swap_quirks(&mut self) {
+        let Some(other) = &mut self.options.quirks_b else {
+            info!("Use --quirks-b to switch quirks");
+            return;
+        };
+        std::mem::swap(&mut self.quirks, other);
+        self.chip.set_quirks(self.quirks);
+        info!("Switched quirks to: {}", quirks_doc::describe(&self.quirks));
+    }
+
+    /// Keep the current state for rewinding, forgetting the oldest state once
+    /// `options.rewind_frames` are kept
+    fn save_rewind_state(&mut self) {
+        if self.options.rewind_frames == 0 {
+            return;
+        }
+        if self.rewind.len() == self.options.rewind_frames {
+            self.rewind.pop_front();
+        }
+        self.rewind.push_back(self.chip.clone());
+    }
 
-            let elapsed_nanos = start.elapsed().as_nanos();
-            if elapsed_nanos < nanos_per_frame {
-                let sleep_duration = u64::try_from(nanos_per_frame - elapsed_nanos).unwrap_or(0);
-                std::thread::sleep(Duration::from_nanos(sleep_duration));
+    /// Go back to the state of the previous frame, if it was kept, and redraw the whole
+    /// display.  The keys that are currently held stay held.
+    fn rewind_frame(&mut self, fg: &[u8], bg: &[u8], texture: &mut Texture) -> Result<(), String> {
+        let Some(state) = self.rewind.pop_back() else {
+            return Ok(());
+        };
+        let held: Vec<u8> = self.chip.keypad.pressed_keys().collect();
+        self.chip = state;
+        for key in 0..KEYPAD_SIZE as u8 {
+            let held = held.contains(&key);
+            if self.chip.keypad.is_pressed(key) != held {
+                self.set_key(key, held);
             }
         }
+
+        let fb = &mut self.chip.fb;
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            fb.write_color_model_rect(fg, bg, Rect::FULL, buffer, pitch);
+        })?;
+        fb.clear_dirty();
+        fb.updated = false;
         Ok(())
     }
 
+    /// Whether the next instruction is a draw operation that has to wait for the next frame.
+    /// `first` is true at the start of the frame, and `drawn` is true once a draw operation
+    /// has modified the screen during the frame.
+    fn draw_deferred(&self, first: bool, drawn: bool) -> bool {
+        if !matches!(self.chip.next_instruction(), Instruction::Draw(..)) {
+            return false;
+        }
+        // With --vblank-wait, drawing waits for the next vertical blank, which is the start
+        // of the next frame.  With --display-wait, only one draw operation can modify the
+        // screen each frame, and the timers keep running while the next one waits.
+        (self.options.vblank_wait && !first) || (self.options.display_wait && drawn)
+    }
+
+    /// Machine cycles available to each frame with VIP timing
+    fn cycle_budget(&self) -> u32 {
+        VIP_CYCLES_PER_SECOND / u32::from(self.options.fps)
+    }
+
+    /// Write the next instruction to stderr, with `frame`, if it is a draw or clear screen
+    /// instruction and draws are logged
+    fn log_draw(&self, frame: u64) {
+        if !self.options.log_draws {
+            return;
+        }
+        match self.chip.next_instruction() {
+            Instruction::Draw(x, y, n) => {
+                let v = self.chip.v();
+                eprintln!(
+                    "Frame {}: {:#06X}: DRAW at ({}, {}), {} rows",
+                    frame,
+                    self.chip.pc(),
+                    v[x],
+                    v[y],
+                    n
+                );
+            }
+            Instruction::Cls => {
+                eprintln!("Frame {}: {:#06X}: CLS", frame, self.chip.pc())
+            }
+            _ => {}
+        }
+    }
+
+    /// Warn about logged writes to the memory the program was loaded into, which are most
+    /// likely self-modifying code.  Each instruction is only reported once.
+    fn warn_self_modify(&mut self) {
+        for (addr, pc) in self.chip.write_log() {
+            if self.program.contains(&addr) && self.self_modifying.insert(pc) {
+                warn!(
+                    "{:#06X}: program modified its own code at {:#06X}",
+                    pc, addr
+                );
+            }
+        }
+        self.chip.clear_write_log();
+    }
+
+    /// Count `instr`, which was just executed at `pc`, if it's an unknown opcode, and warn
+    /// the first time each unknown opcode is executed
+    fn count_unknown(&mut self, pc: usize, instr: Instruction) {
+        let Instruction::Err(opcode) = instr else {
+            return;
+        };
+        let count = self.unknown_opcodes.entry(opcode).or_default();
+        if *count == 0 && self.options.warn_unknown {
+            warn!("{:#06X}: unknown instruction {:#06X} ignored", pc, opcode);
+        }
+        *count += 1;
+    }
+
+    /// Write how many times each unknown opcode was executed to stderr
+    fn dump_unknown_opcodes(&self) {
+        let mut counts: Vec<_> = self.unknown_opcodes.iter().collect();
+        counts.sort();
+        let total: u64 = counts.iter().map(|(_, &count)| count).sum();
+        eprintln!("Ignored {} unknown instructions:", total);
+        for (opcode, count) in counts {
+            eprintln!("{:#06X} {:>12}", opcode, count);
+        }
+    }
+
+    /// Write the hash of the display to stdout, if `frame` frames have run since the hash was
+    /// last written
+    fn print_frame_hash(&self, frame: u64) {
+        if self
+            .options
+            .frame_hash_every
+            .is_some_and(|every| frame.is_multiple_of(every))
+        {
+            println!("Frame {}: {:016x}", frame, self.chip.fb.hash());
+        }
+    }
+
+    /// Write the address and disassembly of the next instruction to stderr
+    fn report_position(&self) {
+        eprintln!("{:#06X}: {}", self.chip.pc(), self.chip.next_instruction());
+    }
+
+    /// Write the registers, the stack, and the memory surrounding the index register to
+    /// stderr
+    fn dump_state(&self) {
+        let chip = &self.chip;
+        eprintln!(
+            "PC: {:#06X}  I: {:#06X}  SP: {:#04X}",
+            chip.pc(),
+            chip.i(),
+            chip.sp()
+        );
+        eprintln!("DT: {:#04X}  ST: {:#04X}", chip.dt, chip.st);
+        for (x, row) in chip.v().chunks(4).enumerate() {
+            let registers: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!("V{:X}: {:#04X}", 4 * x + i, v))
+                .collect();
+            eprintln!("{}", registers.join("  "));
+        }
+        let stack: Vec<String> = chip.stack().iter().map(|a| format!("{:#06X}", a)).collect();
+        eprintln!("Stack: [{}]", stack.join(", "));
+
+        // 256 bytes of memory, aligned to 16 bytes, with the index register in the middle
+        let start = (chip.i().saturating_sub(DUMP_SIZE / 2) & !0xF).min(MEMORY_SIZE - DUMP_SIZE);
+        let _ = hexdump_to(&chip.mem()[start..start + DUMP_SIZE], start, &mut stderr());
+    }
+
+    /// Write a hex dump of the memory starting at the index register to stderr, which is
+    /// usually the sprite or data being worked on
+    fn dump_memory(&self) {
+        let start = self.chip.i().min(MEMORY_SIZE - DUMP_SIZE);
+        let _ = hexdump_to(
+            &self.chip.mem()[start..start + DUMP_SIZE],
+            start,
+            &mut stderr(),
+        );
+    }
+
+    /// Write the call stack to stderr, innermost first
+    fn print_backtrace(&self) {
+        for line in self.backtrace() {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Lines of the call stack, innermost first.  Each line shows where execution is in that
+    /// subroutine, which is a call for all but the innermost, and the subroutine's label in
+    /// the smart disassembly.
+    fn backtrace(&self) -> Vec<String> {
+        let chip = &self.chip;
+        // The stack holds the address of each call, and each subroutine is the target of the
+        // call below it.  The outermost one is the program itself.
+        let mut routines = vec![Some(self.options.start)];
+        routines.extend(chip.stack().iter().map(|&call| {
+            let opcode = chip.mem().get(call..call + 2)?;
+            match Instruction::from(u16::from_be_bytes([opcode[0], opcode[1]])) {
+                Instruction::Call(nnn) => Some(nnn),
+                _ => None,
+            }
+        }));
+        let mut positions = chip.stack().to_vec();
+        positions.push(chip.pc());
+
+        positions
+            .iter()
+            .zip(&routines)
+            .rev()
+            .enumerate()
+            .map(|(depth, (pc, routine))| match routine {
+                Some(routine) => format!("#{} {:#06X} in L_{:04X}", depth, pc, routine),
+                None => format!("#{} {:#06X}", depth, pc),
+            })
+            .collect()
+    }
+
+    /// Write the registers, the stack, a hash of memory, and the display to stdout as JSON.
+    /// The display is a string of `0` and `1` characters, row by row from the top-left corner.
+    fn dump_state_json(&self) {
+        let chip = &self.chip;
+        let fb: String = chip
+            .fb
+            .pixels()
+            .map(|(_, _, set)| if set { '1' } else { '0' })
+            .collect();
+        let state = serde_json::json!({
+            "version": DUMP_VERSION,
+            "pc": chip.pc(),
+            "i": chip.i(),
+            "sp": chip.sp(),
+            "dt": chip.dt,
+            "st": chip.st,
+            "v": chip.v(),
+            "stack": chip.stack(),
+            "memory_sha1": quirks_db::sha1_hex(chip.mem()),
+            "display": {
+                "width": chip.fb.width(),
+                "height": chip.fb.height(),
+                "pixels": fb,
+            },
+        });
+        println!("{}", state);
+    }
+
+    /// Write the number of times each kind of instruction was executed to stderr, from most
+    /// to least frequent
+    fn dump_instruction_counts(&self) {
+        let mut counts: Vec<_> = self.instruction_counts.iter().collect();
+        counts.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+        let total: u64 = counts.iter().map(|(_, &count)| count).sum();
+        eprintln!("{:<5} {:>12} {:>7}", "INSTR", "COUNT", "%");
+        for (mnemonic, &count) in counts {
+            let percent = 100.0 * count as f64 / total as f64;
+            eprintln!("{:<5} {:>12} {:>6.2}%", mnemonic, count, percent);
+        }
+        eprintln!("{:<5} {:>12}", "TOTAL", total);
+    }
+
+    /// Save the current display as a PNG image at `path`
+    fn save_screenshot(&self, path: &Path, fg: &[u8], bg: &[u8]) {
+        let pixels = self.chip.fb.to_color_model(fg, bg);
+        match screenshot::save_png(
+            path,
+            pixels,
+            DISPLAY_WIDTH as u32,
+            DISPLAY_HEIGHT as u32,
+            self.options.scale,
+        ) {
+            Ok(()) => info!("\'{}\': screenshot saved", path.display()),
+            Err(e) => error!(
+                "\'{}\': screenshot could not be saved: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    /// Map a scancode to a CHIP-8 key.  Custom key bindings take precedence over the
+    /// default layout, and keys with a custom binding no longer use their default one.
     fn keymap(&self, scancode: Scancode) -> Option<u8> {
-        match scancode {
-            Scancode::Num1 => Some(0x1),
-            Scancode::Num2 => Some(0x2),
-            Scancode::Num3 => Some(0x3),
-            Scancode::Num4 => Some(0xC),
-            Scancode::Q => Some(0x4),
-            Scancode::W => Some(0x5),
-            Scancode::E => Some(0x6),
-            Scancode::R => Some(0xD),
-            Scancode::A => Some(0x7),
-            Scancode::S => Some(0x8),
-            Scancode::D => Some(0x9),
-            Scancode::F => Some(0xE),
-            Scancode::Z => Some(0xA),
-            Scancode::X => Some(0x0),
-            Scancode::C => Some(0xB),
-            Scancode::V => Some(0xF),
-            _ => None,
-        }
-    }
-}
-
-struct SquareWave {
+        if let Some(&key) = self.options.keymap.get(&scancode) {
+            return Some(key);
+        }
+        default_keymap(scancode).filter(|key| !self.options.keymap.values().any(|k| k == key))
+    }
+
+    /// Map a gamepad button to a CHIP-8 key, the same way as `keymap`
+    fn gamepad_keymap(&self, button: Button) -> Option<u8> {
+        if let Some(&key) = self.options.gamepad_keymap.get(&button) {
+            return Some(key);
+        }
+        default_gamepad_keymap(button)
+            .filter(|key| !self.options.gamepad_keymap.values().any(|k| k == key))
+    }
+}
+
+/// Keys that control the emulator, which can't be mapped to the keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Hotkey {
+    Quit,
+    Turbo,
+    Rewind,
+    Pause,
+    SwapQuirks,
+    Step,
+    FrameAdvance,
+    DumpMemory,
+    Backtrace,
+    StepOver,
+    StepOut,
+    Mute,
+    Fullscreen,
+    Screenshot,
+    NextProgram,
+    Overlay,
+    Record,
+}
+
+/// Scancode of each hotkey
+const HOTKEYS: [(Scancode, Hotkey); 17] = [
+    (Scancode::Escape, Hotkey::Quit),
+    (Scancode::Tab, Hotkey::Turbo),
+    (Scancode::Backspace, Hotkey::Rewind),
+    (Scancode::F5, Hotkey::Pause),
+    (Scancode::F4, Hotkey::SwapQuirks),
+    (Scancode::F6, Hotkey::Step),
+    (Scancode::N, Hotkey::FrameAdvance),
+    (Scancode::H, Hotkey::DumpMemory),
+    (Scancode::B, Hotkey::Backtrace),
+    (Scancode::F7, Hotkey::StepOver),
+    (Scancode::F8, Hotkey::StepOut),
+    (Scancode::M, Hotkey::Mute),
+    (Scancode::F11, Hotkey::Fullscreen),
+    (Scancode::F12, Hotkey::Screenshot),
+    (Scancode::PageDown, Hotkey::NextProgram),
+    (Scancode::F3, Hotkey::Overlay),
+    (Scancode::F9, Hotkey::Record),
+];
+
+impl Hotkey {
+    /// Hotkey of `scancode`, if it has one
+    fn of(scancode: Scancode) -> Option<Self> {
+        HOTKEYS
+            .iter()
+            .find(|&&(s, _)| s == scancode)
+            .map(|&(_, hotkey)| hotkey)
+    }
+
+    /// Whether the hotkey acts again as the operating system repeats it while it's held
+    fn repeats(self) -> bool {
+        matches!(
+            self,
+            Hotkey::Quit
+                | Hotkey::Turbo
+                | Hotkey::Rewind
+                | Hotkey::Step
+                | Hotkey::FrameAdvance
+                | Hotkey::DumpMemory
+                | Hotkey::Backtrace
+                | Hotkey::StepOver
+                | Hotkey::StepOut
+        )
+    }
+}
+
+/// Whether `scancode` is used by a hotkey, so it can't be mapped to the keypad
+pub fn is_hotkey(scancode: Scancode) -> bool {
+    Hotkey::of(scancode).is_some()
+}
+
+/// State of the SDL event loop, which hotkeys and the debugger change
+struct LoopState {
+    /// Close the emulator
+    quit: bool,
+    /// Fast-forward while the turbo key is held
+    turbo: bool,
+    /// Restore the state of earlier frames, one per frame, while the rewind key is held
+    rewinding: bool,
+    /// Nothing is executed and the timers are stopped while paused
+    paused: bool,
+    /// Paused because the window lost the focus, rather than by the user
+    blurred: bool,
+    /// Execute a single instruction while paused
+    single_step: bool,
+    /// Execute a single frame while paused
+    frame_advance: bool,
+    /// Pause once the stack pointer drops below this level, after stepping over or out of a
+    /// subroutine
+    break_below: Option<usize>,
+    /// Whether the buzzer is silenced (press M)
+    muted: bool,
+    /// Switch to the next program at the end of the frame
+    skip_program: bool,
+    #[cfg(feature = "overlay")]
+    show_overlay: bool,
+    /// While recording or replaying input, input is only applied at the start of a frame
+    /// and the timers advance by exactly one frame per frame, so that runs can be repeated
+    /// exactly
+    deterministic: bool,
+    /// Number of frames executed, excluding frames spent paused
+    frame: u64,
+    /// Keypad events received from the keyboard and gamepads, but not yet applied
+    keypad_events: Vec<(u8, bool)>,
+    /// Time that has passed, but not yet been counted down by the timers, which are
+    /// decremented at 60 Hz independent of `fps` and `ipf`
+    timer_accumulator: Duration,
+    /// Notices when the program keeps looping over the same one or two instructions
+    spin: SpinDetector,
+    /// Frames presented and instructions executed since the window title was last updated
+    stats_start: Instant,
+    stats_frames: u32,
+    stats_steps: u64,
+    /// When the running program started
+    program_start: Instant,
+}
+
+impl LoopState {
+    fn new(deterministic: bool) -> Self {
+        Self {
+            quit: false,
+            turbo: false,
+            rewinding: false,
+            paused: false,
+            blurred: false,
+            single_step: false,
+            frame_advance: false,
+            break_below: None,
+            muted: false,
+            skip_program: false,
+            #[cfg(feature = "overlay")]
+            show_overlay: false,
+            deterministic,
+            frame: 0,
+            keypad_events: Vec::new(),
+            timer_accumulator: Duration::ZERO,
+            spin: SpinDetector::default(),
+            stats_start: Instant::now(),
+            stats_frames: 0,
+            stats_steps: 0,
+            program_start: Instant::now(),
+        }
+    }
+
+    /// Mute or unmute the buzzer, which plays at `volume` when unmuted.  Without an audio
+    /// device, only the state changes.
+    fn toggle_mute(&mut self, audio_device: Option<&mut AudioDevice<Buzzer>>, volume: f32) {
+        self.muted = !self.muted;
+        if let Some(audio_device) = audio_device {
+            audio_device.lock().volume = if self.muted { 0.0 } else { volume };
+        }
+    }
+}
+
+/// Window, audio device, recorder, and gamepads of the SDL event loop
+struct Devices {
+    canvas: WindowCanvas,
+    audio_device: Option<AudioDevice<Buzzer>>,
+    recorder: Option<Recorder>,
+    controller_subsystem: GameControllerSubsystem,
+    /// Gamepads that are connected, by instance ID
+    controllers: HashMap<u32, GameController>,
+}
+
+/// Switch between a window sized by `scale` and fullscreen.  In fullscreen, or in the window
+/// if `integer_scale` is true, the display is scaled by the largest integer that fits and
+/// centered, so pixels stay square.
+fn toggle_fullscreen(
+    canvas: &mut WindowCanvas,
+    scale: u32,
+    integer_scale: bool,
+) -> Result<(), String> {
+    const HEIGHT: u32 = DISPLAY_HEIGHT as u32;
+    const WIDTH: u32 = DISPLAY_WIDTH as u32;
+
+    let fullscreen = canvas.window().fullscreen_state() == FullscreenType::Off;
+    if fullscreen {
+        canvas
+            .window_mut()
+            .set_fullscreen(FullscreenType::Desktop)?;
+    } else {
+        canvas.window_mut().set_fullscreen(FullscreenType::Off)?;
+        canvas
+            .window_mut()
+            .set_size(WIDTH * scale, HEIGHT * scale)
+            .map_err(|e| e.to_string())?;
+    }
+    canvas.set_integer_scale(fullscreen || integer_scale)
+}
+
+/// Where the display is drawn and keys are read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    Sdl,
+    Terminal,
+}
+
+/// How many instructions are executed per frame: a fixed number (`--ipf`), or as many as
+/// fit in the time the COSMAC VIP had for each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Timing {
+    Ipf,
+    Vip,
+}
+
+/// Approximate number of machine cycles the COSMAC VIP interpreter took to execute `instr`.
+/// Instructions that skip are charged as if they didn't, and drawing is charged for an
+/// average sprite position.
+fn cycle_cost(instr: &Instruction) -> u32 {
+    match *instr {
+        Instruction::Sys(_) | Instruction::Ret => 10,
+        Instruction::Cls => 24,
+        Instruction::Jmp(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::Skeb(..) | Instruction::Skneb(..) => 10,
+        Instruction::Ske(..) | Instruction::Skne(..) => 14,
+        Instruction::Ldb(..) => 6,
+        Instruction::Addb(..) => 10,
+        Instruction::Ld(..)
+        | Instruction::Or(..)
+        | Instruction::And(..)
+        | Instruction::Xor(..)
+        | Instruction::Add(..)
+        | Instruction::Sub(..)
+        | Instruction::Shr(..)
+        | Instruction::Subr(..)
+        | Instruction::Shl(..) => 44,
+        Instruction::Ldi(_) => 12,
+        Instruction::Jmpz(_) => 22,
+        Instruction::Rnd(..) => 36,
+        Instruction::Draw(_, _, n) => 68 + 42 * u32::from(n),
+        Instruction::Skp(_) | Instruction::Sknp(_) => 14,
+        Instruction::Ldft(_) | Instruction::Ldk(_) => 10,
+        Instruction::Lddt(_) | Instruction::Ldst(_) => 10,
+        Instruction::Addi(_) => 16,
+        Instruction::Font(_) => 20,
+        Instruction::Bcd(_) => 204,
+        Instruction::Sreg(x) | Instruction::Lreg(x) => 28 + 14 * (x as u32 + 1),
+        Instruction::Err(_) => 10,
+    }
+}
+
+/// What to do when the program calls a machine code routine (opcode 0x0nnn), which cannot
+/// be emulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SysTrap {
+    Log,
+    Pause,
+    Error,
+}
+
+/// Shape of the buzzer's tone
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Saw,
+}
+
+impl Waveform {
+    /// Amplitude of the wave, from -1.0 to 1.0, at `phase` (from 0.0 to 1.0) of a period
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (2.0 * std::f32::consts::PI * phase).sin(),
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+            Waveform::Saw => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+/// Default layout of the CHIP-8 keypad on a gamepad.  Most programs use 2, 4, 6, and 8
+/// as directions, and 5 as the action key.
+fn default_gamepad_keymap(button: Button) -> Option<u8> {
+    match button {
+        Button::DPadUp => Some(0x2),
+        Button::DPadLeft => Some(0x4),
+        Button::DPadRight => Some(0x6),
+        Button::DPadDown => Some(0x8),
+        Button::A => Some(0x5),
+        Button::B => Some(0x0),
+        Button::X => Some(0x7),
+        Button::Y => Some(0x9),
+        Button::LeftShoulder => Some(0x1),
+        Button::RightShoulder => Some(0x3),
+        Button::Back => Some(0xE),
+        Button::Start => Some(0xF),
+        _ => None,
+    }
+}
+
+struct Buzzer {
     channels: usize,
-    half_period: i64,
     volume: f32,
-    index: i64,
+    /// Position within the current period of the wave, from 0.0 to 1.0
+    phase: f32,
+    /// Change in phase per sample, which is the pitch divided by the sample rate
+    phase_step: f32,
+    waveform: Waveform,
+    /// Whether the buzzer should be sounding
+    gate: Arc<AtomicBool>,
+    /// Current level of the volume envelope, from 0.0 (silent) to 1.0
+    envelope: f32,
+    /// Change in the envelope level per sample while it ramps up or down
+    envelope_step: f32,
 }
 
-impl AudioCallback for SquareWave {
+impl AudioCallback for Buzzer {
     type Channel = f32;
 
     fn callback(&mut self, out: &mut [Self::Channel]) {
         for x in out.chunks_mut(self.channels) {
-            if self.index / self.half_period >= 2 {
-                self.index = 0;
+            // Ramp the volume up and down, instead of cutting the wave off abruptly, to avoid
+            // clicks when the buzzer starts and stops
+            if self.gate.load(Ordering::Relaxed) {
+                self.envelope = (self.envelope + self.envelope_step).min(1.0);
+            } else {
+                self.envelope = (self.envelope - self.envelope_step).max(0.0);
             }
+            let sample = self.volume * self.envelope * self.waveform.sample(self.phase);
             for vol in x {
-                *vol = if self.index / self.half_period == 0 {
-                    self.volume
-                } else {
-                    -self.volume
-                };
+                *vol = sample;
             }
-            self.index += 1;
+            // Any fraction of a sample left over at the end of a period carries into the next,
+            // so the pitch stays accurate even when it doesn't divide the sample rate
+            self.phase = (self.phase + self.phase_step).fract();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{PROGRAM_START, STACK_SIZE};
+
+    /// Buzzer sounding at full volume with `waveform` at `pitch`, for one channel at
+    /// `freq` samples per second
+    fn buzzer(waveform: Waveform, pitch: f32, freq: f32) -> Buzzer {
+        Buzzer {
+            channels: 1,
+            volume: 1.0,
+            phase: 0.0,
+            phase_step: pitch / freq,
+            waveform,
+            gate: Arc::new(AtomicBool::new(true)),
+            envelope: 1.0,
+            envelope_step: 0.0,
+        }
+    }
+
+    #[test]
+    fn buzzer_plays_at_the_requested_pitch() {
+        // Pitches that don't divide the sample rate, where rounding the period drifts
+        for pitch in [55.0, 440.0, 1234.0] {
+            let mut buzzer = buzzer(Waveform::Square, pitch, 44100.0);
+            let mut out = vec![0.0; 44100];
+            buzzer.callback(&mut out);
+            // One rising edge per period, over one second
+            let periods = out.windows(2).filter(|w| w[0] < 0.0 && w[1] > 0.0).count();
+            assert!(
+                (periods as f32 - pitch).abs() <= 1.0,
+                "{} periods at {} Hz",
+                periods,
+                pitch
+            );
+        }
+    }
+
+    #[test]
+    fn mutes_without_an_audio_device() {
+        let mut state = LoopState::new(false);
+        state.toggle_mute(None, 0.5);
+        assert!(state.muted);
+        state.toggle_mute(None, 0.5);
+        assert!(!state.muted);
+    }
+
+    /// Options for running programs headless, with every debugging feature turned off
+    fn options() -> Options {
+        Options {
+            title: "test".into(),
+            playlist: Vec::new(),
+            rom_duration: None,
+            start: PROGRAM_START,
+            font: None,
+            stack_size: STACK_SIZE,
+            patches: Vec::new(),
+            backend: Backend::Sdl,
+            fps: 60,
+            ipf: 10,
+            timing: Timing::Ipf,
+            scale: 10,
+            fg: 0xFFFFFFFF,
+            bg: 0x000000FF,
+            pitch: 440,
+            waveform: Waveform::Square,
+            volume: 1.0,
+            no_audio: true,
+            display_wait: false,
+            vblank_wait: false,
+            record: None,
+            record_input: None,
+            play_input: None,
+            rewind_frames: 0,
+            seed: None,
+            #[cfg(feature = "overlay")]
+            overlay_font: None,
+            keymap: HashMap::new(),
+            gamepad_keymap: HashMap::new(),
+            mouse_key: None,
+            dump_state: false,
+            screenshot_on_exit: None,
+            json: false,
+            trace: false,
+            trace_to: None,
+            step_log: None,
+            trace_limit: None,
+            profile_instructions: false,
+            log_draws: false,
+            frame_hash_every: None,
+            halt_on_spin: false,
+            trap_sys: None,
+            watchpoints: Vec::new(),
+            warn_self_modify: false,
+            warn_unknown: false,
+            no_clear: false,
+            ghosting: false,
+            crt_gradient: None,
+            vsync: false,
+            adaptive_speed: false,
+            pause_on_blur: false,
+            integer_scale: false,
+            quirks_b: None,
         }
     }
+
+    /// Emulator running `program`, given as opcodes, with `options`
+    fn emulator(program: &[u16], options: Options) -> Emulator {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        Emulator::new(&rom, options, Quirks::default()).unwrap()
+    }
+
+    #[test]
+    fn warns_about_self_modifying_code() {
+        let options = Options {
+            warn_self_modify: true,
+            ..options()
+        };
+        // V0 is stored over the first instruction, and then outside of the program
+        let mut emulator = emulator(&[0xA200, 0xF055, 0xA300, 0xF055, 0x1208], options);
+        emulator.run_headless(5).unwrap();
+        assert_eq!(emulator.self_modifying, HashSet::from([0x202]));
+    }
+
+    #[test]
+    fn display_wait_defers_the_second_draw_of_a_frame() {
+        let display_wait = Options {
+            display_wait: true,
+            ..options()
+        };
+        // DT = 60, then draw the glyph for 0 twice at (0, 0)
+        let program = [0x623C, 0xF215, 0xA000, 0xD015, 0xD015];
+        let mut emu = emulator(&program, display_wait);
+        emu.run_headless(4).unwrap();
+        // The second draw waits for the next frame, which ends the first one early
+        assert_eq!(emu.chip.pc(), PROGRAM_START + 8);
+        // Drawing twice would have erased the glyph
+        assert!(emu.chip.fb.pixel(0, 0));
+        assert_eq!(emu.chip.dt, 59);
+
+        // The next frame starts with the deferred draw
+        emu.run_headless(1).unwrap();
+        assert_eq!(emu.chip.pc(), PROGRAM_START + 10);
+        assert!(!emu.chip.fb.pixel(0, 0));
+    }
+
+    #[test]
+    fn vblank_wait_defers_draws_to_the_start_of_a_frame() {
+        let vblank_wait = Options {
+            vblank_wait: true,
+            ..options()
+        };
+        // DT = 60, then draw the glyph for 0 at (0, 0)
+        let mut emu = emulator(&[0x623C, 0xF215, 0xD015], vblank_wait);
+        emu.run_headless(2).unwrap();
+        // The draw waits for the start of the next frame
+        assert_eq!(emu.chip.pc(), PROGRAM_START + 4);
+        assert_eq!(emu.chip.dt, 59);
+        emu.run_headless(1).unwrap();
+        assert!(emu.chip.fb.pixel(0, 0));
+    }
+
+    #[test]
+    fn headless_timers_tick_at_60_hz() {
+        // DT = 60, then loop forever
+        let program = [0x603C, 0xF015, 0x1204];
+        for (fps, ipf) in [(60, 10), (30, 20), (120, 5), (50, 12)] {
+            let options = Options {
+                fps,
+                ipf,
+                ..options()
+            };
+            let mut headless = emulator(&program, options);
+            // Half a second of simulated time
+            headless
+                .run_headless(u64::from(fps) * u64::from(ipf) / 2)
+                .unwrap();
+            assert_eq!(headless.chip.dt, 30, "{} fps", fps);
+        }
+    }
+
+    #[test]
+    fn backtrace_lists_nested_calls() {
+        // 0x200 calls 0x204, which calls 0x208, which loops forever
+        let mut nested = emulator(&[0x2204, 0x0000, 0x2208, 0x0000, 0x1208], options());
+        nested.run_headless(3).unwrap();
+        assert_eq!(
+            nested.backtrace(),
+            [
+                "#0 0x0208 in L_0208",
+                "#1 0x0204 in L_0204",
+                "#2 0x0200 in L_0200"
+            ]
+        );
+    }
+
+    /// Hash of the display after each of `frames` headless frames of a program that draws
+    /// the glyph for 0 at random positions
+    fn frame_hashes(seed: u64, frames: usize) -> Vec<u64> {
+        let options = Options {
+            seed: Some(seed),
+            ..options()
+        };
+        let program = [0xC03F, 0xC11F, 0xA000, 0xD015, 0x1200];
+        let mut headless = emulator(&program, options);
+        (0..frames)
+            .map(|_| {
+                headless.run_headless(10).unwrap();
+                headless.chip.fb.hash()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_runs_have_identical_frame_hashes() {
+        let hashes = frame_hashes(42, 50);
+        assert_eq!(hashes, frame_hashes(42, 50));
+        assert_ne!(hashes, frame_hashes(43, 50));
+        // The display changes from frame to frame
+        assert!(hashes.windows(2).all(|pair| pair[0] != pair[1]));
+    }
 }
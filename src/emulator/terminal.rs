@@ -0,0 +1,187 @@
+use std::{
+    collections::HashMap,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal,
+};
+use sdl2::keyboard::Scancode;
+
+use super::{cycle_cost, Emulator, Timing};
+use crate::chip8::TIMER_FREQUENCY;
+use crate::instruction::Instruction;
+
+/// Number of frames a key stays pressed after the terminal reports it.  Most terminals never
+/// report key releases, so keys are released automatically unless repeated.
+const KEY_HOLD_FRAMES: u32 = 6;
+/// Characters drawn for a set pixel.  Terminal cells are about twice as tall as they are
+/// wide, so each pixel is two cells wide.
+const SET_PIXEL: &str = "██";
+/// Characters drawn for an unset pixel
+const UNSET_PIXEL: &str = "  ";
+
+impl Emulator {
+    /// Run the program in the terminal, drawing the display with block characters instead of
+    /// opening a window
+    pub(super) fn run_terminal(&mut self) -> Result<(), String> {
+        let mut out = stdout();
+        terminal::enable_raw_mode().map_err(|e| e.to_string())?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide).map_err(|e| e.to_string())?;
+
+        let result = self.run_terminal_loop(&mut out);
+
+        // Restore the terminal, even if the program failed
+        execute!(out, cursor::Show, terminal::LeaveAlternateScreen)
+            .and_then(|()| terminal::disable_raw_mode())
+            .map_err(|e| e.to_string())?;
+        result
+    }
+
+    fn run_terminal_loop(&mut self, out: &mut impl Write) -> Result<(), String> {
+        let frame_period = Duration::from_secs(1) / u32::from(self.options.fps);
+        let timer_period = Duration::from_secs(1) / TIMER_FREQUENCY;
+        let mut timer_accumulator = Duration::ZERO;
+        // Frames left until each pressed key is released
+        let mut held_keys: HashMap<u8, u32> = HashMap::new();
+        let mut paused = false;
+        // Number of frames executed, excluding frames spent paused
+        let mut frame: u64 = 0;
+        // Nothing has been drawn to the terminal yet
+        let mut redraw = true;
+
+        loop {
+            let start = Instant::now();
+            while event::poll(Duration::ZERO).map_err(|e| e.to_string())? {
+                let Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    ..
+                }) = event::read().map_err(|e| e.to_string())?
+                else {
+                    continue;
+                };
+                match code {
+                    KeyCode::Esc => return Ok(()),
+                    // Raw mode disables the interrupt signal
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                        return Ok(())
+                    }
+                    KeyCode::F(5) if kind == KeyEventKind::Press => {
+                        paused = !paused;
+                    }
+                    KeyCode::Char(c) => {
+                        let Some(key) = self.terminal_keymap(c) else {
+                            continue;
+                        };
+                        if kind == KeyEventKind::Release {
+                            held_keys.remove(&key);
+                            self.chip.keypad.key_released(key);
+                        } else {
+                            if !held_keys.contains_key(&key) {
+                                self.chip.keypad.key_pressed(key);
+                            }
+                            held_keys.insert(key, KEY_HOLD_FRAMES);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            // Machine cycles spent by the frame, with VIP timing
+            let mut cycles = 0;
+            // Whether a draw operation has modified the screen during the frame
+            let mut drawn = false;
+            for n in 0..self.batch_size() {
+                if paused || self.chip.waiting_for_key() || self.draw_deferred(n == 0, drawn) {
+                    break;
+                }
+                self.log_draw(frame);
+                let pc = self.chip.pc();
+                let instr = self.chip.step()?;
+                drawn |= matches!(instr, Instruction::Draw(..)) && self.chip.fb.updated;
+                self.warn_self_modify();
+                self.count_unknown(pc, instr);
+                if self.options.profile_instructions {
+                    *self.instruction_counts.entry(instr.mnemonic()).or_default() += 1;
+                }
+                if self.chip.fb.updated {
+                    self.chip.fb.updated = false;
+                    redraw = true;
+                }
+                if self.options.timing == Timing::Vip {
+                    cycles += cycle_cost(&instr);
+                    if cycles >= self.cycle_budget() {
+                        break;
+                    }
+                }
+            }
+
+            if !paused {
+                frame += 1;
+                self.print_frame_hash(frame);
+            }
+
+            if redraw {
+                self.draw_terminal(out).map_err(|e| e.to_string())?;
+                redraw = false;
+            }
+
+            held_keys.retain(|&key, frames| {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.chip.keypad.key_released(key);
+                }
+                *frames > 0
+            });
+
+            let elapsed = start.elapsed();
+            if !paused {
+                self.adapt_speed(elapsed, frame_period);
+            }
+            if elapsed < frame_period {
+                std::thread::sleep(frame_period - elapsed);
+            }
+            if !paused {
+                timer_accumulator += start.elapsed();
+            }
+            while timer_accumulator >= timer_period {
+                self.chip.tick_timers();
+                timer_accumulator -= timer_period;
+            }
+        }
+    }
+
+    /// Draw the whole display to the terminal, one row of pixels per line
+    fn draw_terminal(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        let fb = &self.chip.fb;
+        queue!(out, cursor::MoveTo(0, 0))?;
+        for y in 0..fb.height() {
+            let row: String = (0..fb.width())
+                .map(|x| {
+                    if fb.pixel(x, y) {
+                        SET_PIXEL
+                    } else {
+                        UNSET_PIXEL
+                    }
+                })
+                .collect();
+            queue!(out, Print(row), cursor::MoveToNextLine(1))?;
+        }
+        self.chip.fb.clear_dirty();
+        out.flush()
+    }
+
+    /// CHIP-8 key of a character typed in the terminal, using the same layout (including
+    /// custom bindings) as the physical keys of the keyboard
+    fn terminal_keymap(&self, c: char) -> Option<u8> {
+        let scancode = Scancode::from_name(&c.to_ascii_uppercase().to_string())?;
+        self.keymap(scancode)
+    }
+}
@@ -0,0 +1,87 @@
+/// Number of glyphs in the default font
+pub const GLYPH_COUNT: usize = 16;
+/// Size (in bytes) of the glyphs in the default font
+pub const GLYPH_SIZE: usize = 5;
+/// Size (in bytes) of a font
+pub const FONT_SIZE: usize = GLYPH_SIZE * GLYPH_COUNT;
+/// Default font
+pub const FONT_DATA: [u8; GLYPH_SIZE * GLYPH_COUNT] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+/// Address of the font in memory
+pub const FONT_ADDRESS: usize = 0;
+
+/// Number of glyphs in the big (SUPER-CHIP) font, which only has decimal digits
+pub const BIG_GLYPH_COUNT: usize = 10;
+/// Size (in bytes) of the glyphs in the big font
+pub const BIG_GLYPH_SIZE: usize = 10;
+/// Size (in bytes) of the big font
+pub const BIG_FONT_SIZE: usize = BIG_GLYPH_SIZE * BIG_GLYPH_COUNT;
+/// Big font, 8 pixels wide and 10 pixels tall
+pub const BIG_FONT_DATA: [u8; BIG_GLYPH_SIZE * BIG_GLYPH_COUNT] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+];
+/// Address of the big font in memory, right after the default font
+pub const BIG_FONT_ADDRESS: usize = FONT_ADDRESS + FONT_SIZE;
+
+/// Address in memory of the glyph for `digit` in the big font if `big` is set, or else in
+/// the default font.  `None` if the font has no such glyph.
+pub fn glyph_address(digit: u8, big: bool) -> Option<usize> {
+    let digit = usize::from(digit);
+    if big {
+        (digit < BIG_GLYPH_COUNT).then(|| BIG_FONT_ADDRESS + BIG_GLYPH_SIZE * digit)
+    } else {
+        (digit < GLYPH_COUNT).then(|| FONT_ADDRESS + GLYPH_SIZE * digit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyphs_are_stored_one_after_another() {
+        let addresses: Vec<_> = (0..0x10).map(|digit| glyph_address(digit, false)).collect();
+        let expected = [
+            0x00, 0x05, 0x0A, 0x0F, 0x14, 0x19, 0x1E, 0x23, 0x28, 0x2D, 0x32, 0x37, 0x3C, 0x41,
+            0x46, 0x4B,
+        ];
+        assert_eq!(addresses, expected.map(Some));
+        // The glyph for 1, as stored in memory
+        assert_eq!(FONT_DATA[0x05..0x0A], [0x20, 0x60, 0x20, 0x20, 0x70]);
+        assert_eq!(glyph_address(0x10, false), None);
+    }
+
+    #[test]
+    fn big_glyphs_follow_the_default_font() {
+        let addresses: Vec<_> = (0..10).map(|digit| glyph_address(digit, true)).collect();
+        let expected = [0x50, 0x5A, 0x64, 0x6E, 0x78, 0x82, 0x8C, 0x96, 0xA0, 0xAA];
+        assert_eq!(addresses, expected.map(Some));
+        // The big font only has decimal digits
+        assert_eq!(glyph_address(0xA, true), None);
+    }
+}
@@ -6,12 +6,58 @@ use std::{
 use crate::chip8::DISPLAY_HEIGHT as HEIGHT;
 use crate::chip8::DISPLAY_WIDTH as WIDTH;
 
-#[derive(Debug)]
+/// A rectangular region of the display, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    /// The entire display
+    pub const FULL: Self = Self {
+        x: 0,
+        y: 0,
+        width: WIDTH,
+        height: HEIGHT,
+    };
+
+    /// Smallest rectangle containing both `self` and the pixel at `(x, y)`
+    fn including(self, x: usize, y: usize) -> Self {
+        let left = min(self.x, x);
+        let top = min(self.y, y);
+        let right = max(self.x + self.width, x + 1);
+        let bottom = max(self.y + self.height, y + 1);
+        Self {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Framebuffer {
     /// Pixel buffer
     buffer: [bool; HEIGHT * WIDTH],
     /// Display has been updated.  Set this to false after redrawing the screen.  
     pub updated: bool,
+    /// Bounding box of the pixels modified since the dirty region was last cleared
+    dirty: Option<Rect>,
+    /// Brightness of each pixel, from 0 (unset) to 255 (set).  Unset pixels fade out
+    /// gradually, instead of instantly, when ghosting is enabled.
+    intensity: [u8; HEIGHT * WIDTH],
+    /// Number of frames each pixel has stayed set since it was last flipped
+    age: [u8; HEIGHT * WIDTH],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Framebuffer {
@@ -19,39 +65,100 @@ impl Framebuffer {
         Self {
             buffer: [false; HEIGHT * WIDTH],
             updated: false,
+            // Nothing has been drawn to the screen yet
+            dirty: Some(Rect::FULL),
+            intensity: [0; HEIGHT * WIDTH],
+            age: [0; HEIGHT * WIDTH],
         }
     }
 
+    /// Width of the display in pixels
+    pub fn width(&self) -> usize {
+        WIDTH
+    }
+
+    /// Height of the display in pixels
+    pub fn height(&self) -> usize {
+        HEIGHT
+    }
+
+    /// Whether the pixel at `(x, y)` is set.  Coordinates outside of the display wrap around.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self[(x, y)]
+    }
+
+    /// Every pixel as `(x, y, set)`, row by row from the top-left corner
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, bool)> + '_ {
+        self.buffer
+            .iter()
+            .enumerate()
+            .map(|(index, &pixel)| (index % WIDTH, index / WIDTH, pixel))
+    }
+
+    /// Hash of the pixels, which is the same on every platform and in every run, for
+    /// comparing displays (64-bit FNV-1a)
+    pub fn hash(&self) -> u64 {
+        self.buffer
+            .iter()
+            .fold(0xCBF2_9CE4_8422_2325, |hash, &pixel| {
+                (hash ^ u64::from(pixel)).wrapping_mul(0x0100_0000_01B3)
+            })
+    }
+
     /// Unset all pixels
     pub fn clear(&mut self) {
         self.buffer.copy_from_slice(&[false; HEIGHT * WIDTH]);
         self.updated = true;
+        self.dirty = Some(Rect::FULL);
+    }
+
+    /// Bounding box of the pixels modified since `clear_dirty` was last called, or `None`
+    /// if no pixels were modified
+    pub fn dirty_rect(&self) -> Option<Rect> {
+        self.dirty
+    }
+
+    /// Reset the dirty region.  Call this after redrawing the dirty region of the screen.
+    pub fn clear_dirty(&mut self) {
+        self.dirty = None;
     }
 
     /// Draw a sprite at `(x,y)` that has a width of 8 pixels and height of `n` pixels.
-    /// `sprite` contains the sprite data.  Sprites drawn at the edge of the screen will be
+    /// `sprite` contains the sprite data.  The starting coordinate always wraps, so a sprite
+    /// drawn at `x = 70` starts at `x = 6`.  Sprites drawn at the edge of the screen will be
     /// clipped if `wrap` is false; otherwise, sprites will get drawn at the right coordinates
-    /// on the other side of the screen.  Returns true if any pixels are flipped from set
-    /// to unset.
-    pub fn draw(&mut self, x: u8, y: u8, n: u8, sprite: &[u8], wrap: bool) -> bool {
+    /// on the other side of the screen.  Returns the number of rows of the sprite in which
+    /// any pixels are flipped from set to unset.
+    pub fn draw(&mut self, x: u8, y: u8, n: u8, sprite: &[u8], wrap: bool) -> u8 {
         let n = usize::from(n);
         assert_eq!(sprite.len(), n);
 
+        // The starting position always wraps.  When clipping, the loops stop at the edges,
+        // so off-screen pixels are neither drawn nor counted as collisions.  When wrapping,
+        // the loops run past the edges and indexing wraps each pixel to the other side,
+        // independently for each axis, which also handles sprites straddling a corner.
         let x = usize::from(x) % WIDTH;
         let y = usize::from(y) % HEIGHT;
         let max_x = if wrap { x + 8 } else { min(x + 8, WIDTH) };
         let max_y = if wrap { y + n } else { min(y + n, HEIGHT) };
-        let mut ret = false;
+        let mut ret = 0;
 
-        for i in x..max_x {
-            for j in y..max_y {
+        for j in y..max_y {
+            let mut collision = false;
+            for i in x..max_x {
                 let sprite_pixel = ((sprite[j - y] >> (7 - (i - x))) & 0x1) == 1;
                 if sprite_pixel && self[(i, j)] {
                     self[(i, j)] = false;
-                    ret = true;
+                    collision = true;
                 } else if sprite_pixel && !self[(i, j)] {
                     self[(i, j)] = true;
                 }
+                if sprite_pixel {
+                    self.age[(j % HEIGHT) * WIDTH + i % WIDTH] = 0;
+                }
+            }
+            if collision {
+                ret += 1;
             }
         }
 
@@ -76,6 +183,115 @@ impl Framebuffer {
 
         ret
     }
+
+    /// Like `to_color_model`, but writes into the caller-owned buffer `out` instead of
+    /// allocating.  `fg` and `bg` must be the same length, and `out` must hold exactly one
+    /// of them per pixel.
+    pub fn write_color_model<T>(&self, fg: &[T], bg: &[T], out: &mut [T])
+    where
+        T: Clone,
+    {
+        assert_eq!(fg.len(), bg.len());
+        assert_eq!(out.len(), fg.len() * HEIGHT * WIDTH);
+
+        for (&pixel, color) in self.buffer.iter().zip(out.chunks_exact_mut(fg.len())) {
+            if pixel {
+                color.clone_from_slice(fg);
+            } else {
+                color.clone_from_slice(bg);
+            }
+        }
+    }
+
+    /// Update the brightness of each pixel: set pixels are fully lit, while unset pixels
+    /// fade by `amount`.  Call this once per frame when ghosting is enabled.
+    pub fn decay_intensity(&mut self, amount: u8) {
+        for (&pixel, intensity) in self.buffer.iter().zip(self.intensity.iter_mut()) {
+            *intensity = if pixel {
+                u8::MAX
+            } else {
+                intensity.saturating_sub(amount)
+            };
+        }
+    }
+
+    /// Like `write_color_model`, but blends between `bg` and `fg` by the brightness of each
+    /// pixel, so that fading pixels are shown as a mix of both colors
+    pub fn write_blended_color_model(&self, fg: &[u8], bg: &[u8], out: &mut [u8]) {
+        assert_eq!(fg.len(), bg.len());
+        assert_eq!(out.len(), fg.len() * HEIGHT * WIDTH);
+
+        for (&intensity, color) in self.intensity.iter().zip(out.chunks_exact_mut(fg.len())) {
+            let intensity = u16::from(intensity);
+            for ((channel, &fg), &bg) in color.iter_mut().zip(fg).zip(bg) {
+                let (fg, bg) = (u16::from(fg), u16::from(bg));
+                *channel = ((fg * intensity + bg * (255 - intensity)) / 255) as u8;
+            }
+        }
+    }
+
+    /// Count another frame for every set pixel, and reset the age of unset pixels.  Call this
+    /// once per frame when pixels are colored by age.
+    pub fn age_pixels(&mut self) {
+        for (&pixel, age) in self.buffer.iter().zip(self.age.iter_mut()) {
+            *age = if pixel { age.saturating_add(1) } else { 0 };
+        }
+    }
+
+    /// Like `write_color_model`, but set pixels blend from `new` to `old` over their first
+    /// `max_age` frames, so that pixels that stay lit change color
+    pub fn write_aged_color_model(
+        &self,
+        new: &[u8],
+        old: &[u8],
+        bg: &[u8],
+        max_age: u8,
+        out: &mut [u8],
+    ) {
+        assert_eq!(new.len(), bg.len());
+        assert_eq!(old.len(), bg.len());
+        assert_eq!(out.len(), bg.len() * HEIGHT * WIDTH);
+
+        let max_age = u16::from(max_age.max(1));
+        let pixels = self.buffer.iter().zip(&self.age);
+        for ((&pixel, &age), color) in pixels.zip(out.chunks_exact_mut(bg.len())) {
+            if !pixel {
+                color.copy_from_slice(bg);
+                continue;
+            }
+            let age = u16::from(age).min(max_age);
+            for ((channel, &new), &old) in color.iter_mut().zip(new).zip(old) {
+                let (new, old) = (u16::from(new), u16::from(old));
+                *channel = ((old * age + new * (max_age - age)) / max_age) as u8;
+            }
+        }
+    }
+
+    /// Like `write_color_model`, but only writes the pixels inside `rect`.  Each row of
+    /// `rect` starts `pitch` elements after the previous row in `out`.
+    pub fn write_color_model_rect<T>(
+        &self,
+        fg: &[T],
+        bg: &[T],
+        rect: Rect,
+        out: &mut [T],
+        pitch: usize,
+    ) where
+        T: Clone,
+    {
+        assert_eq!(fg.len(), bg.len());
+
+        for (j, row) in out.chunks_mut(pitch).take(rect.height).enumerate() {
+            let row = &mut row[..rect.width * fg.len()];
+            for (i, color) in row.chunks_exact_mut(fg.len()).enumerate() {
+                if self[(rect.x + i, rect.y + j)] {
+                    color.clone_from_slice(fg);
+                } else {
+                    color.clone_from_slice(bg);
+                }
+            }
+        }
+    }
 }
 
 impl Index<(usize, usize)> for Framebuffer {
@@ -93,6 +309,79 @@ impl IndexMut<(usize, usize)> for Framebuffer {
         self.updated = true;
         let x = index.0 % WIDTH;
         let y = index.1 % HEIGHT;
+        self.dirty = Some(match self.dirty {
+            Some(rect) => rect.including(x, y),
+            None => Rect {
+                x,
+                y,
+                width: 1,
+                height: 1,
+            },
+        });
         &mut self.buffer[y * WIDTH + x]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels_match_the_color_model() {
+        let mut fb = Framebuffer::new();
+        fb.draw(10, 5, 3, &[0xA5, 0x3C, 0xFF], false);
+        let colors = fb.to_color_model(&[1u8], &[0]);
+        assert_eq!(fb.pixels().count(), fb.width() * fb.height());
+        for (x, y, set) in fb.pixels() {
+            assert_eq!(colors[y * fb.width() + x], u8::from(set), "({}, {})", x, y);
+            assert_eq!(fb.pixel(x, y), set);
+        }
+        assert_eq!(fb.pixels().filter(|&(_, _, set)| set).count(), 4 + 4 + 8);
+    }
+
+    /// Coordinates of the pixels that are set, row by row
+    fn set_pixels(fb: &Framebuffer) -> Vec<(usize, usize)> {
+        fb.pixels()
+            .filter_map(|(x, y, set)| set.then_some((x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn sprites_are_clipped_at_the_edges() {
+        let mut fb = Framebuffer::new();
+        // Only the 4x2 corner of an 8x4 sprite at (60, 30) is on the screen
+        assert_eq!(fb.draw(60, 30, 4, &[0xFF; 4], false), 0);
+        let corner: Vec<_> = (30..32)
+            .flat_map(|y| (60..64).map(move |x| (x, y)))
+            .collect();
+        assert_eq!(set_pixels(&fb), corner);
+        // Only the rows on the screen collide
+        assert_eq!(fb.draw(60, 30, 4, &[0xFF; 4], false), 2);
+        assert!(set_pixels(&fb).is_empty());
+    }
+
+    #[test]
+    fn sprites_wrap_around_both_edges() {
+        let mut fb = Framebuffer::new();
+        // An 8x4 sprite at (60, 30) covers a 4x2 block in each corner
+        assert_eq!(fb.draw(60, 30, 4, &[0xFF; 4], true), 0);
+        let mut expected = Vec::new();
+        for y in [0, 1, 30, 31] {
+            expected.extend((0..4).chain(60..64).map(|x| (x, y)));
+        }
+        assert_eq!(set_pixels(&fb), expected);
+        // Every row collides, including those wrapped to the top
+        assert_eq!(fb.draw(60, 30, 4, &[0xFF; 4], true), 4);
+        assert!(set_pixels(&fb).is_empty());
+    }
+
+    #[test]
+    fn starting_coordinate_always_wraps() {
+        for wrap in [false, true] {
+            let mut fb = Framebuffer::new();
+            // (70, 35) wraps to (6, 3), whether or not the sprite itself wraps
+            assert_eq!(fb.draw(70, 35, 1, &[0xC0], wrap), 0);
+            assert_eq!(set_pixels(&fb), [(6, 3), (7, 3)], "wrap: {}", wrap);
+        }
+    }
+}
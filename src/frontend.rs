@@ -0,0 +1,60 @@
+//! Traits for the display, input, and audio of a frontend, so that the virtual machine can
+//! run anywhere, e.g., in a browser when compiled to WebAssembly
+
+use crate::{
+    chip8::{Chip8, Keypad},
+    framebuffer::Framebuffer,
+};
+
+#[cfg(feature = "native")]
+pub mod sdl;
+
+/// Shows the display of the virtual machine
+pub trait Display {
+    /// Show `fb`, which changed since it was last shown.  The pixels that changed are
+    /// inside `fb.dirty_rect()`.
+    fn draw(&mut self, fb: &Framebuffer) -> Result<(), String>;
+}
+
+/// Reads keypad input
+pub trait Input {
+    /// Apply the keys pressed and released since the last call to `keypad`.  Returns
+    /// `false` once the user asks to quit.
+    fn poll(&mut self, keypad: &mut Keypad) -> Result<bool, String>;
+}
+
+/// Plays the buzzer
+pub trait Audio {
+    /// Start or stop the buzzer
+    fn set_buzzer(&mut self, on: bool);
+}
+
+/// Run a single frame on a frontend: read the input, execute up to `ipf` instructions, tick
+/// the timers once, and draw the display if it changed.  Call this 60 times per second, the
+/// frequency of the timers, e.g., from a browser's animation frame callback.  Returns
+/// `false` once the input asks to quit.
+pub fn run_frame(
+    chip: &mut Chip8,
+    ipf: u16,
+    display: &mut impl Display,
+    input: &mut impl Input,
+    audio: &mut impl Audio,
+) -> Result<bool, String> {
+    if !input.poll(&mut chip.keypad)? {
+        return Ok(false);
+    }
+    for _ in 0..ipf {
+        if chip.waiting_for_key() {
+            break;
+        }
+        chip.step()?;
+    }
+    chip.tick_timers();
+    if chip.fb.updated {
+        display.draw(&chip.fb)?;
+        chip.fb.clear_dirty();
+        chip.fb.updated = false;
+    }
+    audio.set_buzzer(chip.st > 0);
+    Ok(true)
+}
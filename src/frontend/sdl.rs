@@ -0,0 +1,127 @@
+//! Implementations of the frontend traits with SDL, as used by the `ocho` binary
+
+use sdl2::{
+    audio::{AudioCallback, AudioDevice},
+    event::Event,
+    keyboard::Scancode,
+    rect::Rect,
+    render::Texture,
+    EventPump,
+};
+
+use super::{Audio, Display, Input};
+use crate::{chip8::Keypad, framebuffer::Framebuffer};
+
+/// Draws the display to a streaming texture in the RGBA32 format, the same size as the
+/// display.  Only the pixels changed since the display was last shown are copied.
+pub struct TextureDisplay<'a, 't> {
+    texture: &'a mut Texture<'t>,
+    /// Color of set pixels, as an RGBA value
+    fg: [u8; 4],
+    /// Color of unset pixels, as an RGBA value
+    bg: [u8; 4],
+}
+
+impl<'a, 't> TextureDisplay<'a, 't> {
+    pub fn new(texture: &'a mut Texture<'t>, fg: [u8; 4], bg: [u8; 4]) -> Self {
+        Self { texture, fg, bg }
+    }
+}
+
+impl Display for TextureDisplay<'_, '_> {
+    fn draw(&mut self, fb: &Framebuffer) -> Result<(), String> {
+        let Some(rect) = fb.dirty_rect() else {
+            return Ok(());
+        };
+        let sdl_rect = Rect::new(
+            rect.x as i32,
+            rect.y as i32,
+            rect.width as u32,
+            rect.height as u32,
+        );
+        let (fg, bg) = (&self.fg, &self.bg);
+        self.texture
+            .with_lock(sdl_rect, |buffer: &mut [u8], pitch: usize| {
+                fb.write_color_model_rect(fg, bg, rect, buffer, pitch);
+            })
+    }
+}
+
+/// Reads the keypad from the keyboard, using `default_keymap`.  Closing the window or
+/// pressing Esc quits.
+pub struct KeyboardInput {
+    event_pump: EventPump,
+}
+
+impl KeyboardInput {
+    pub fn new(event_pump: EventPump) -> Self {
+        Self { event_pump }
+    }
+}
+
+impl Input for KeyboardInput {
+    fn poll(&mut self, keypad: &mut Keypad) -> Result<bool, String> {
+        for event in self.event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    ..
+                } => return Ok(false),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(key) = default_keymap(scancode) {
+                        keypad.key_pressed(key);
+                    }
+                }
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } => {
+                    if let Some(key) = default_keymap(scancode) {
+                        keypad.key_released(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The buzzer plays by resuming the audio device, and stops by pausing it
+impl<C: AudioCallback> Audio for AudioDevice<C> {
+    fn set_buzzer(&mut self, on: bool) {
+        if on {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+}
+
+/// Default layout of the CHIP-8 keypad, using the left-hand side of a QWERTY keyboard
+pub fn default_keymap(scancode: Scancode) -> Option<u8> {
+    match scancode {
+        Scancode::Num1 => Some(0x1),
+        Scancode::Num2 => Some(0x2),
+        Scancode::Num3 => Some(0x3),
+        Scancode::Num4 => Some(0xC),
+        Scancode::Q => Some(0x4),
+        Scancode::W => Some(0x5),
+        Scancode::E => Some(0x6),
+        Scancode::R => Some(0xD),
+        Scancode::A => Some(0x7),
+        Scancode::S => Some(0x8),
+        Scancode::D => Some(0x9),
+        Scancode::F => Some(0xE),
+        Scancode::Z => Some(0xA),
+        Scancode::X => Some(0x0),
+        Scancode::C => Some(0xB),
+        Scancode::V => Some(0xF),
+        _ => None,
+    }
+}
@@ -0,0 +1,117 @@
+use std::{
+    fmt::{Display, Formatter},
+    io::BufRead,
+};
+
+use crate::chip8::KEYPAD_SIZE;
+
+/// A key on the keypad being pressed or released at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    /// Number of frames executed before the event
+    pub frame: u64,
+    /// Key on the keypad, from 0x0 to 0xF
+    pub key: u8,
+    /// The key was pressed, rather than released
+    pub pressed: bool,
+}
+
+impl InputEvent {
+    /// Parse an event in the format written by `Display` (e.g., `120 A down`)
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [frame, key, state] = fields[..] else {
+            return Result::Err(format!("expected FRAME KEY down|up, found '{}'", s));
+        };
+        let frame = frame
+            .parse()
+            .map_err(|_| format!("{} is not a valid frame number", frame))?;
+        let key = u8::from_str_radix(key, 16)
+            .ok()
+            .filter(|&key| usize::from(key) < KEYPAD_SIZE)
+            .ok_or_else(|| format!("{} is not a valid key", key))?;
+        let pressed = match state {
+            "down" => true,
+            "up" => false,
+            _ => return Result::Err(format!("{} is not down or up", state)),
+        };
+        Ok(Self {
+            frame,
+            key,
+            pressed,
+        })
+    }
+}
+
+impl Display for InputEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let state = if self.pressed { "down" } else { "up" };
+        write!(f, "{} {:X} {}", self.frame, self.key, state)
+    }
+}
+
+/// Read events written one per line, sorted by frame.  Blank lines are ignored.
+pub fn read_events<R: BufRead>(input: R) -> Result<Vec<InputEvent>, String> {
+    let mut events = Vec::new();
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event = InputEvent::parse(&line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+        events.push(event);
+    }
+    // Events in the same frame keep their order
+    events.sort_by_key(|event| event.frame);
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_events_round_trip() {
+        let events = [
+            InputEvent {
+                frame: 0,
+                key: 0x5,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 12,
+                key: 0x5,
+                pressed: false,
+            },
+            InputEvent {
+                frame: 12,
+                key: 0xA,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 300,
+                key: 0xA,
+                pressed: false,
+            },
+        ];
+        let recording: String = events.iter().map(|event| format!("{}\n", event)).collect();
+        assert_eq!(recording, "0 5 down\n12 5 up\n12 A down\n300 A up\n");
+        assert_eq!(read_events(recording.as_bytes()).unwrap(), events);
+    }
+
+    #[test]
+    fn events_are_sorted_by_frame() {
+        let events = read_events("7 1 down\n\n3 2 down\n7 1 up\n".as_bytes()).unwrap();
+        let order: Vec<_> = events.iter().map(|e| (e.frame, e.key, e.pressed)).collect();
+        assert_eq!(order, [(3, 2, true), (7, 1, true), (7, 1, false)]);
+    }
+
+    #[test]
+    fn malformed_events_are_rejected() {
+        for line in ["1 G down", "x 1 down", "1 1 left", "1 1"] {
+            assert!(InputEvent::parse(line).is_err(), "{}", line);
+        }
+        let e = read_events("1 1 down\n2 1 sideways\n".as_bytes()).unwrap_err();
+        assert!(e.starts_with("line 2:"), "{}", e);
+    }
+}
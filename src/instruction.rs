@@ -9,7 +9,7 @@ use Instruction::*;
 ///  - x - a 4-bit value, the lower 4 bits of the high byte of the instruction
 ///  - y - a 4-bit value, the upper 4 bits of the low byte of the instruction
 ///  - nn - an 8-bit value, the lowest 8 bits of the instruction
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum Instruction {
     /// 0nnn - SYS nnn. Jump to machine code routine at nnn (ignored in modern interpreters).
     Sys(usize),
@@ -155,6 +155,144 @@ impl From<u16> for Instruction {
     }
 }
 
+impl Instruction {
+    /// Encode the instruction as a 16-bit opcode.  This is the inverse of
+    /// `From<u16> for Instruction`.  Returns `None` for `Err`, since it is not a valid
+    /// instruction.
+    pub fn encode(&self) -> Option<u16> {
+        let x = |x: usize| ((x & 0xF) as u16) << 8;
+        let y = |y: usize| ((y & 0xF) as u16) << 4;
+        let nnn = |nnn: usize| (nnn & 0xFFF) as u16;
+
+        let opcode = match *self {
+            Sys(a) => nnn(a),
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Jmp(a) => 0x1000 | nnn(a),
+            Call(a) => 0x2000 | nnn(a),
+            Skeb(vx, nn) => 0x3000 | x(vx) | u16::from(nn),
+            Skneb(vx, nn) => 0x4000 | x(vx) | u16::from(nn),
+            Ske(vx, vy) => 0x5000 | x(vx) | y(vy),
+            Ldb(vx, nn) => 0x6000 | x(vx) | u16::from(nn),
+            Addb(vx, nn) => 0x7000 | x(vx) | u16::from(nn),
+            Ld(vx, vy) => 0x8000 | x(vx) | y(vy),
+            Or(vx, vy) => 0x8001 | x(vx) | y(vy),
+            And(vx, vy) => 0x8002 | x(vx) | y(vy),
+            Xor(vx, vy) => 0x8003 | x(vx) | y(vy),
+            Add(vx, vy) => 0x8004 | x(vx) | y(vy),
+            Sub(vx, vy) => 0x8005 | x(vx) | y(vy),
+            Shr(vx, vy) => 0x8006 | x(vx) | y(vy),
+            Subr(vx, vy) => 0x8007 | x(vx) | y(vy),
+            Shl(vx, vy) => 0x800E | x(vx) | y(vy),
+            Skne(vx, vy) => 0x9000 | x(vx) | y(vy),
+            Ldi(a) => 0xA000 | nnn(a),
+            Jmpz(a) => 0xB000 | nnn(a),
+            Rnd(vx, nn) => 0xC000 | x(vx) | u16::from(nn),
+            Draw(vx, vy, n) => 0xD000 | x(vx) | y(vy) | u16::from(n & 0xF),
+            Skp(vx) => 0xE09E | x(vx),
+            Sknp(vx) => 0xE0A1 | x(vx),
+            Ldft(vx) => 0xF007 | x(vx),
+            Ldk(vx) => 0xF00A | x(vx),
+            Lddt(vx) => 0xF015 | x(vx),
+            Ldst(vx) => 0xF018 | x(vx),
+            Addi(vx) => 0xF01E | x(vx),
+            Font(vx) => 0xF029 | x(vx),
+            Bcd(vx) => 0xF033 | x(vx),
+            Sreg(vx) => 0xF055 | x(vx),
+            Lreg(vx) => 0xF065 | x(vx),
+            Err(_) => return None,
+        };
+
+        Some(opcode)
+    }
+
+    /// Address that the instruction jumps to or calls, or `None` if it doesn't change the
+    /// flow of execution to a fixed address.  For `Jmpz`, V0 is added to the address (or VX,
+    /// the high nibble of the address, with the jumping quirk).
+    #[must_use]
+    pub fn target_address(&self) -> Option<usize> {
+        match *self {
+            Jmp(nnn) | Call(nnn) | Jmpz(nnn) => Some(nnn),
+            _ => None,
+        }
+    }
+
+    /// Registers read by the instruction.  This includes registers that are only read with
+    /// some quirks enabled (e.g., Vy of `Shr`).
+    #[must_use]
+    pub fn reads_registers(&self) -> Vec<usize> {
+        match *self {
+            Skeb(x, _) | Skneb(x, _) | Addb(x, _) => vec![x],
+            Ske(x, y) | Skne(x, y) | Or(x, y) | And(x, y) | Xor(x, y) => vec![x, y],
+            Add(x, y) | Sub(x, y) | Shr(x, y) | Subr(x, y) | Shl(x, y) => vec![x, y],
+            Ld(_, y) => vec![y],
+            // With the jumping quirk, VX is read instead, where X is the high nibble of nnn
+            Jmpz(nnn) if nnn >> 8 != 0 => vec![0, nnn >> 8],
+            Jmpz(_) => vec![0],
+            Draw(x, y, _) => vec![x, y],
+            Skp(x) | Sknp(x) | Lddt(x) | Ldst(x) | Addi(x) | Font(x) | Bcd(x) => vec![x],
+            Sreg(x) => (0..=x).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Registers written by the instruction.  This includes registers that are only written
+    /// with some quirks enabled (e.g., VF of `Or`).
+    #[must_use]
+    pub fn writes_registers(&self) -> Vec<usize> {
+        match *self {
+            Ldb(x, _) | Addb(x, _) | Ld(x, _) | Rnd(x, _) | Ldft(x) | Ldk(x) => vec![x],
+            Or(x, _) | And(x, _) | Xor(x, _) | Add(x, _) | Sub(x, _) => vec![x, 0xF],
+            Shr(x, _) | Subr(x, _) | Shl(x, _) => vec![x, 0xF],
+            Draw(..) | Addi(_) => vec![0xF],
+            Lreg(x) => (0..=x).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Mnemonic of the instruction, as displayed by the disassembler
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Sys(_) => "SYS",
+            Cls => "CLS",
+            Ret => "RET",
+            Jmp(_) => "JMP",
+            Call(_) => "CALL",
+            Skeb(..) => "SKEB",
+            Skneb(..) => "SKNEB",
+            Ske(..) => "SKE",
+            Ldb(..) => "LDB",
+            Addb(..) => "ADDB",
+            Ld(..) => "LD",
+            Or(..) => "OR",
+            And(..) => "AND",
+            Xor(..) => "XOR",
+            Add(..) => "ADD",
+            Sub(..) => "SUB",
+            Shr(..) => "SHR",
+            Subr(..) => "SUBR",
+            Shl(..) => "SHL",
+            Skne(..) => "SKNE",
+            Ldi(_) => "LDI",
+            Jmpz(_) => "JMPZ",
+            Rnd(..) => "RND",
+            Draw(..) => "DRAW",
+            Skp(_) => "SKP",
+            Sknp(_) => "SKNP",
+            Ldft(_) => "LDFT",
+            Ldk(_) => "LDK",
+            Lddt(_) => "LDDT",
+            Ldst(_) => "LDST",
+            Addi(_) => "ADDI",
+            Font(_) => "FONT",
+            Bcd(_) => "BCD",
+            Sreg(_) => "SREG",
+            Lreg(_) => "LREG",
+            Err(_) => "ERR",
+        }
+    }
+}
+
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -197,3 +335,52 @@ impl Display for Instruction {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_inverts_decode() {
+        for opcode in 0..=u16::MAX {
+            match Instruction::from(opcode) {
+                Err(_) => assert_eq!(Err(opcode).encode(), None),
+                instr => assert_eq!(instr.encode(), Some(opcode), "{}", instr),
+            }
+        }
+    }
+
+    #[test]
+    fn jumps_and_calls_have_target_addresses() {
+        assert_eq!(Instruction::from(0x1234).target_address(), Some(0x234));
+        assert_eq!(Instruction::from(0x2ABC).target_address(), Some(0xABC));
+        assert_eq!(Instruction::from(0xB345).target_address(), Some(0x345));
+        assert_eq!(Instruction::from(0x00EE).target_address(), None);
+        assert_eq!(Instruction::from(0xA234).target_address(), None);
+    }
+
+    #[test]
+    fn reads_and_writes_registers() {
+        let registers = |opcode: u16| {
+            let instr = Instruction::from(opcode);
+            (instr.reads_registers(), instr.writes_registers())
+        };
+        // LDB V3, 0x12
+        assert_eq!(registers(0x6312), (vec![], vec![3]));
+        // ADD V1, V2
+        assert_eq!(registers(0x8124), (vec![1, 2], vec![1, 0xF]));
+        // LD V1, V2
+        assert_eq!(registers(0x8120), (vec![2], vec![1]));
+        // DRAW V4, V5, 6
+        assert_eq!(registers(0xD456), (vec![4, 5], vec![0xF]));
+        // JMPZ 0x345 reads V0, or V3 with the jumping quirk
+        assert_eq!(registers(0xB345), (vec![0, 3], vec![]));
+        // JMPZ 0x045 reads V0 either way
+        assert_eq!(registers(0xB045), (vec![0], vec![]));
+        // SREG V2 and LREG V2
+        assert_eq!(registers(0xF255), (vec![0, 1, 2], vec![]));
+        assert_eq!(registers(0xF265), (vec![], vec![0, 1, 2]));
+        // CLS
+        assert_eq!(registers(0x00E0), (vec![], vec![]));
+    }
+}
@@ -0,0 +1,12 @@
+//! CHIP-8 virtual machine, assembler, and disassembler, independent of any display or
+//! audio backend.  The SDL implementations of the frontend traits in `frontend::sdl` need
+//! the `native` feature.
+
+pub mod assembler;
+pub mod chip8;
+pub mod disassembler;
+pub mod font;
+pub mod framebuffer;
+pub mod frontend;
+pub mod input;
+pub mod instruction;
@@ -0,0 +1,39 @@
+use std::env;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log messages to stderr.  Informational messages are written as they are, and
+/// all other messages are prefixed with their level.
+struct Logger;
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        match record.level() {
+            Level::Info => eprintln!("{}", record.args()),
+            Level::Warn => eprintln!("warning: {}", record.args()),
+            level => eprintln!("{}: {}", level.as_str().to_lowercase(), record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+/// Install the logger.  Messages above `level` are dropped.  If `level` isn't given, it's
+/// read from the `RUST_LOG` environment variable (e.g., `RUST_LOG=debug`), and otherwise
+/// defaults to informational messages.
+pub fn init(level: Option<LevelFilter>) {
+    let level = level
+        .or_else(|| env::var("RUST_LOG").ok()?.parse().ok())
+        .unwrap_or(LevelFilter::Info);
+    log::set_logger(&LOGGER).expect("Logger is only installed once");
+    log::set_max_level(level);
+}
@@ -1,29 +1,129 @@
-mod chip8;
+mod config;
 mod emulator;
-mod framebuffer;
-mod instruction;
+mod logger;
+#[cfg(feature = "overlay")]
+mod overlay;
+mod quirks_db;
+mod quirks_doc;
+mod recorder;
+mod screenshot;
+mod spin;
+mod themes;
+mod tracer;
+mod watch;
 
-use chip8::{Quirks, PROGRAM_START};
-use clap::{value_parser, Parser};
-use emulator::{Emulator, Options};
+use ocho::{assembler, chip8, disassembler, font, framebuffer, frontend, input, instruction};
+
+use chip8::{Chip8, Quirks, KEYPAD_SIZE, MEMORY_SIZE, PRESETS, STACK_SIZE};
+use clap::{
+    error::ErrorKind, parser::ValueSource, value_parser, ArgGroup, ArgMatches, Command,
+    CommandFactory, FromArgMatches, Parser, ValueEnum,
+};
+use config::Config;
+use disassembler::{disassemble_smart_to, disassemble_to, hexdump_to, reachable_instructions};
+use emulator::{Backend, Emulator, Options, SysTrap, Timing, Waveform};
+use font::{FONT_DATA, FONT_SIZE, GLYPH_SIZE};
 use instruction::Instruction;
-use std::{fs::read, path::PathBuf};
+use log::{info, LevelFilter};
+use sdl2::{controller::Button, keyboard::Scancode};
+use std::{
+    collections::HashMap,
+    fs::{read, read_to_string, write, File},
+    hash::Hash,
+    io,
+    io::{stdin, stdout, BufWriter, Read, Write},
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use themes::THEMES;
+use watch::Watchpoint;
 
 /// A simple CHIP-8 emulator and disassembler
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
+#[command(group(ArgGroup::new("json_output").args(["bench", "dump_state_on_exit"]).multiple(true)))]
 struct Cli {
-    /// Path to the binary CHIP-8 program
-    program: PathBuf,
+    /// Path to the binary CHIP-8 program, or - to read the program from stdin.  Given more
+    /// than one program, press Page Down to switch to the next program
+    #[arg(required_unless_present_any = ["assemble", "list_quirks", "list_themes", "dump_font"])]
+    programs: Vec<PathBuf>,
+    /// Assemble a CHIP-8 source file into a binary program, instead of running a program
+    #[arg(long, value_name = "SOURCE", conflicts_with = "programs")]
+    assemble: Option<PathBuf>,
+    /// Output path of the assembled program [default: SOURCE with a .ch8 extension]
+    #[arg(short, long, value_name = "OUT", requires = "assemble")]
+    output: Option<PathBuf>,
+    /// Load settings from a TOML configuration file.  Options given on the command line take
+    /// precedence over the configuration file
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
     /// Display disassembly code before running the binary CHIP-8 program
     #[arg(long)]
     disasm: bool,
+    /// Like --disasm, but only decode instructions that are reachable from the start of the
+    /// program, displaying everything else as data
+    #[arg(long, conflicts_with = "disasm")]
+    disasm_smart: bool,
+    /// Write the disassembly code to a file, instead of displaying it
+    #[arg(long, value_name = "PATH")]
+    disasm_out: Option<PathBuf>,
+    /// Display a hex dump of the binary CHIP-8 program before running it
+    #[arg(long)]
+    hexdump: bool,
+    /// Report invalid instructions that are reachable from the start of the program, and how
+    /// often each instruction appears, instead of running the program.  Exits with an error
+    /// if any invalid instructions are found
+    #[arg(long)]
+    check: bool,
+    /// Only disassemble the addresses from START up to, but not including, END (e.g.,
+    /// 0x200:0x240)
+    #[arg(long, value_name = "START:END", value_parser = parse_range)]
+    disasm_range: Option<Range<usize>>,
+    /// Start decoding instructions N bytes into the program, displaying the bytes before
+    /// them as data (e.g., 1 to realign the disassembly after a data section of odd length)
+    #[arg(long, value_name = "N", conflicts_with = "disasm_smart")]
+    disasm_offset: Option<usize>,
+    /// Address the program is loaded at and starts running from (e.g., 0x600 for ETI-660
+    /// programs)
+    #[arg(long, value_name = "ADDR", default_value = "0x200", value_parser = parse_start)]
+    start: usize,
+    /// Replace the default font with an 80 byte binary file (16 glyphs of 5 bytes each)
+    #[arg(long, value_name = "FILE")]
+    font: Option<PathBuf>,
+    /// Leave the memory of the font zeroed, as on hardware without a built-in font.  Programs
+    /// that draw digits with FX29 show nothing, while programs that bring their own font are
+    /// unaffected
+    #[arg(long, conflicts_with = "font")]
+    no_font: bool,
+    /// Print every glyph of the built-in font as a bitmap, e.g., as a starting point for --font
+    #[arg(long)]
+    dump_font: bool,
+    /// Write bytes over memory after loading the program (e.g., 0x2A0=00E0 writes a CLS
+    /// instruction at 0x2A0).  Can be used multiple times
+    #[arg(long = "patch", value_name = "ADDR=BYTES", value_parser = parse_patch)]
+    patches: Vec<(usize, Vec<u8>)>,
+    /// Switch to the next program after this many seconds, when given more than one program
+    #[arg(long, value_name = "SECS", value_parser = parse_duration)]
+    rom_duration: Option<f64>,
+    /// Number of nested subroutine calls allowed.  Sizes other than 16 are non-standard, but
+    /// some programs nest calls more deeply
+    #[arg(long, value_name = "N", default_value_t = STACK_SIZE, value_parser = parse_stack_size)]
+    stack_size: usize,
+    /// Draw the display in a window, or in the terminal with block characters
+    #[arg(long, value_enum, default_value_t = Backend::Sdl)]
+    backend: Backend,
     /// Target frames per second
     #[arg(short, long, default_value_t = 60, value_parser = value_parser!(u16).range(1..))]
     fps: u16,
     /// Target instructions per frame
     #[arg(short, long, default_value_t = 10, value_parser = value_parser!(u16).range(1..))]
     ipf: u16,
+    /// Execute --ipf instructions per frame, or as many as the COSMAC VIP had time for,
+    /// charging each instruction its approximate cost on the original hardware
+    #[arg(long, value_enum, default_value_t = Timing::Ipf)]
+    timing: Timing,
     /// Window scale factor
     #[arg(short, long, default_value_t = 10, value_parser = value_parser!(u32).range(1..))]
     scale: u32,
@@ -33,111 +133,856 @@ struct Cli {
     /// Background color in RGBA8888 format (e.g., #FF0A2B1D or 0xFF0A2B1D)
     #[arg(short, long, default_value_t = String::from("0x000000"), value_parser=verify_color)]
     background: String,
+    /// Use a named pair of colors.  --color and --background override the theme's colors
+    #[arg(long, value_parser = THEMES.map(|theme| theme.name))]
+    theme: Option<String>,
+    /// Print every theme and its colors
+    #[arg(long)]
+    list_themes: bool,
     /// Pitch of the buzzer (in Hz)
     #[arg(short, long, default_value_t = 440, value_parser = value_parser!(u16).range(20..=10_000))]
     pitch: u16,
-    /// Limit one draw operation per frame
+    /// Shape of the buzzer's tone
+    #[arg(long, value_enum, default_value_t = Waveform::Square)]
+    waveform: Waveform,
+    /// Don't play any sound, or open an audio device
+    #[arg(long)]
+    no_audio: bool,
+    /// Volume of the buzzer, from 0.0 to 1.0 (press M to mute and unmute)
+    #[arg(long, default_value_t = 0.25, value_parser = parse_volume)]
+    volume: f32,
+    /// Limit one draw operation per frame.  Later draw operations wait for the next frame
     #[arg(short, long)]
     display_wait: bool,
-    /// Bitwise operations reset the flags register
+    /// Delay each draw operation until the start of the next frame, like the COSMAC VIP.
+    /// Stricter than --display-wait, which allows the draw operation anywhere in the frame
+    #[arg(long, conflicts_with = "display_wait")]
+    vblank_wait: bool,
+    /// Fade pixels out over a few frames, instead of instantly, to reduce flicker
     #[arg(long)]
-    quirk_vf_reset: bool,
-    /// Save and load instructions increment the index register
+    ghosting: bool,
+    /// Change set pixels from the foreground color to this color (in RGBA8888 format) the
+    /// longer they stay set, for a CRT-like look
+    #[arg(long, value_name = "COLOR", value_parser = verify_color, conflicts_with = "ghosting")]
+    crt_gradient: Option<String>,
+    /// Present frames in sync with the display's refresh rate.  Programs still run at the
+    /// target frames per second
     #[arg(long)]
-    quirk_memory: bool,
-    /// Sprites drawn to the screen wrap, instead of clip
+    vsync: bool,
+    /// Execute fewer instructions per frame when frames take too long to emulate, so the
+    /// target frames per second is kept on slower machines
+    #[arg(long, conflicts_with = "vsync")]
+    adaptive_speed: bool,
+    /// Pause, and silence the buzzer, while the window is in the background
     #[arg(long)]
-    quirk_wrap: bool,
-    /// Bitwise shifting operations use two registers, instead of only one
+    pause_on_blur: bool,
+    /// Only scale the display by whole numbers when the window is resized, adding borders as
+    /// needed, so pixels stay crisp.  Fullscreen always does this
     #[arg(long)]
-    quirk_shift: bool,
-    /// Jump with offset instruction uses specified register, instead of V0
+    integer_scale: bool,
+    /// Map a CHIP-8 key to a keyboard scancode (e.g., 5=Up), overriding the default layout.
+    /// Can be used multiple times
+    #[arg(long = "map", value_name = "KEY=SCANCODE", value_parser = parse_key_binding)]
+    key_bindings: Vec<(u8, Scancode)>,
+    /// Map a CHIP-8 key to a gamepad button (e.g., 5=a or 2=dpup), overriding the default
+    /// layout.  Can be used multiple times
+    #[arg(long = "gamepad-map", value_name = "KEY=BUTTON", value_parser = parse_gamepad_binding)]
+    gamepad_bindings: Vec<(u8, Button)>,
+    /// Press a CHIP-8 key (e.g., 5) while the left mouse button is held.  Mouse input is not
+    /// part of any CHIP-8 platform, and is meant for experimental programs
+    #[arg(long, value_name = "KEY", value_parser = parse_key)]
+    mouse: Option<u8>,
+    /// Record gameplay to an animated GIF (press F9 to start and stop recording)
+    #[arg(long, value_name = "GIF")]
+    record: Option<PathBuf>,
+    /// Record keypad input to a file, for replaying with --play-input
+    #[arg(long, value_name = "FILE")]
+    record_input: Option<PathBuf>,
+    /// Replay keypad input recorded with --record-input, instead of reading the keyboard.
+    /// Replays are exact when given the same --seed and --ipf as the recording
+    #[arg(long, value_name = "FILE", conflicts_with = "record_input")]
+    play_input: Option<PathBuf>,
+    /// Keep the state of every frame from the last SECS seconds, so that holding Backspace
+    /// rewinds the program
+    #[arg(
+        long,
+        value_name = "SECS",
+        value_parser = parse_duration,
+        conflicts_with_all = ["record_input", "play_input"]
+    )]
+    rewind_seconds: Option<f64>,
+    /// Seed the random number generator, so that runs can be repeated
+    #[arg(long)]
+    seed: Option<u64>,
+    /// TrueType font of the debugging overlay, which shows the registers, timers, and upcoming
+    /// instructions (press F3 to show and hide the overlay)
+    #[cfg(feature = "overlay")]
+    #[arg(long, value_name = "TTF")]
+    overlay_font: Option<PathBuf>,
+    /// Print the registers, stack, and memory around the index register on exit
+    #[arg(long)]
+    dump_state_on_exit: bool,
+    /// Save the display as a PNG image when the emulator exits.  With --cycles, this renders
+    /// a program for a fixed number of instructions, e.g., to compare against a known image
+    #[arg(long, value_name = "FILE")]
+    screenshot_on_exit: Option<PathBuf>,
+    /// Log every executed instruction, along with the registers it changed
+    #[arg(long)]
+    trace: bool,
+    /// Write the instruction log to a file, instead of stderr
+    #[arg(long, value_name = "FILE", requires = "trace")]
+    trace_to: Option<PathBuf>,
+    /// Stop logging after this many instructions
+    #[arg(long, value_name = "LINES", requires = "trace")]
+    trace_limit: Option<u64>,
+    /// Execute this many instructions as fast as possible, without opening a window, then exit
+    #[arg(long, value_name = "N")]
+    cycles: Option<u64>,
+    /// Log the address and opcode of every instruction executed by --cycles to a CSV file,
+    /// ending with the final registers, for comparing runs
+    #[arg(long, value_name = "FILE", requires = "cycles")]
+    step_log: Option<PathBuf>,
+    /// Print the number of instructions executed per second by --cycles
+    #[arg(long, requires = "cycles")]
+    bench: bool,
+    /// Print the --bench results and the --dump-state-on-exit state as JSON to stdout
+    #[arg(long, requires = "json_output")]
+    json: bool,
+    /// Most detailed messages to print: off, error, warn, info, debug, or trace [default: the
+    /// RUST_LOG environment variable, or info]
+    #[arg(long, value_name = "LEVEL", value_parser = parse_log_level)]
+    log_level: Option<LevelFilter>,
+    /// Count how many times each kind of instruction is executed, and print the counts on exit
     #[arg(long)]
-    quirk_jump: bool,
+    profile_instructions: bool,
+    /// Print the frame number and position of every DRAW, and every CLS, as it is executed
+    #[arg(long)]
+    log_draws: bool,
+    /// Print a hash of the display every N frames, e.g., to compare runs given the same --seed
+    /// and --play-input, or the same --cycles
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    frame_hash_every: Option<u64>,
+    /// Pause when the program halts in an infinite loop over one or two instructions, e.g.,
+    /// jumping to itself (press F5 to resume)
+    #[arg(long)]
+    halt_on_spin: bool,
+    /// Report calls to machine code routines (SYS), which are ignored by default, and then
+    /// continue, pause, or stop with an error
+    #[arg(long, value_enum, value_name = "ACTION")]
+    trap_sys: Option<SysTrap>,
+    /// Pause when a register (e.g., V3) or memory address (e.g., 0x2F0) changes.  Can be used
+    /// multiple times
+    #[arg(long = "watch", value_name = "LOCATION", value_parser = Watchpoint::parse)]
+    watchpoints: Vec<Watchpoint>,
+    /// Warn when the program writes to its own code (self-modifying code)
+    #[arg(long)]
+    warn_self_modify: bool,
+    /// Warn the first time each opcode that isn't a valid instruction is executed, and print
+    /// how many times each one was executed on exit.  Such opcodes are otherwise ignored
+    #[arg(long)]
+    warn_unknown: bool,
+    /// Ignore CLS instructions, so that everything drawn stays visible, and print how many
+    /// were ignored on exit.  Useful for studying how a program draws
+    #[arg(long)]
+    no_clear: bool,
+    /// Print every quirk, the instructions it affects, and the platforms that use it
+    #[arg(long)]
+    list_quirks: bool,
+    /// Use the quirks of a platform.  The --quirk-* options turn individual quirks on or off
+    /// (e.g., --profile schip --quirk-jump=false)
+    #[arg(long, value_parser = PRESETS)]
+    profile: Option<String>,
+    /// A second set of quirks to switch to and from while running (press F4), to compare how
+    /// a program behaves with each.  A comma-separated list of profiles and quirks, named
+    /// without the --quirk- prefix (e.g., cosmac or chip8,shift,jump)
+    #[arg(long, value_name = "QUIRKS", value_parser = quirks_doc::parse_quirks)]
+    quirks_b: Option<Quirks>,
+    /// Don't apply the recommended settings of programs found in the built-in database
+    #[arg(long)]
+    no_auto_quirks: bool,
+    /// Look up programs in this JSON database before the built-in database
+    #[arg(long, value_name = "FILE", conflicts_with = "no_auto_quirks")]
+    quirks_db: Option<PathBuf>,
+    /// Bitwise operations reset the flags register
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_vf_reset: Option<bool>,
+    /// Save and load instructions increment the index register
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_memory: Option<bool>,
+    /// Sprites drawn to the screen wrap, instead of clip
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_wrap: Option<bool>,
+    /// Bitwise shifting operations use two registers, instead of only one
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_shift: Option<bool>,
+    /// Jump with offset instruction uses specified register, instead of V0
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_jump: Option<bool>,
+    /// Wait for key instruction completes when a key is released, instead of pressed
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_key_release: Option<bool>,
+    /// Draw instruction sets VF to the number of sprite rows that collided, instead of 0 or 1
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_collision_rows: Option<bool>,
+    /// Add to index instruction sets VF when the index register overflows past the end of
+    /// memory
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_addi_overflow: Option<bool>,
+    /// Draw instruction draws nothing when the sprite starts off the screen, instead of
+    /// wrapping the starting coordinate
+    #[arg(
+        long,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    quirk_clip_origin: Option<bool>,
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let matches = Cli::command().get_matches();
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    logger::init(cli.log_level);
 
-    let rom = match read(&cli.program) {
-        Ok(rom) => rom,
-        Err(err) => {
+    // Whether the instructions per frame and colors were chosen by the user, rather than the
+    // defaults
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let mut ipf_given = from_cli("ipf");
+    let mut color_given = from_cli("color");
+    let mut background_given = from_cli("background");
+    if let Some(path) = cli.config.clone() {
+        if let Err(e) = Config::load(&path).and_then(|config| {
+            ipf_given |= config.ipf.is_some();
+            color_given |= config.color.is_some();
+            background_given |= config.background.is_some();
+            apply_config(&mut cli, &matches, config)
+        }) {
             eprintln!(
-                "\'{}\': file could not be opened: {}",
-                cli.program.display(),
+                "\'{}\': not a valid configuration file: {}",
+                path.display(),
+                e
+            );
+            return;
+        }
+    }
+
+    if cli.list_quirks {
+        quirks_doc::print_quirks();
+        return;
+    }
+
+    if cli.list_themes {
+        themes::print_themes();
+        return;
+    }
+
+    if cli.dump_font {
+        print_font();
+        return;
+    }
+
+    if let Some(source) = &cli.assemble {
+        let output = match &cli.output {
+            Some(output) => output.clone(),
+            None => source.with_extension("ch8"),
+        };
+        assemble(source, &output);
+        return;
+    }
+
+    // Clap requires a program unless assembling or listing quirks, themes, or the font.
+    // Everything but running applies to the first program only.
+    let mut programs = mem::take(&mut cli.programs).into_iter();
+    let program = programs.next().expect("Verified by clap");
+    let Some(rom) = read_rom(&program) else {
+        return;
+    };
+    let mut playlist = Vec::new();
+    for path in programs {
+        let Some(rom) = read_rom(&path) else {
+            return;
+        };
+        if let Err(err) = Chip8::new(&rom, Quirks::default(), cli.start) {
+            eprintln!(
+                "\'{}\': not a valid CHIP-8 program: {}",
+                path.display(),
                 err
             );
             return;
         }
+        playlist.push((program_title(&path), rom));
+    }
+
+    let font = match &cli.font {
+        Some(path) => match read(path) {
+            Ok(font) if font.len() == FONT_SIZE => Some(font),
+            Ok(font) => {
+                eprintln!(
+                    "\'{}\': not a valid font: expected {} bytes, found {}",
+                    path.display(),
+                    FONT_SIZE,
+                    font.len()
+                );
+                return;
+            }
+            Err(err) => {
+                eprintln!("\'{}\': file could not be opened: {}", path.display(), err);
+                return;
+            }
+        },
+        None if cli.no_font => Some(vec![0; FONT_SIZE]),
+        None => None,
     };
 
-    if rom.is_empty() {
-        eprintln!(
-            "\'{}\': not a valid CHIP-8 program: file is empty",
-            cli.program.display()
-        );
+    if let Some(range) = &cli.disasm_range {
+        let end = cli.start + rom.len().next_multiple_of(2);
+        if range.start < cli.start || range.end > end {
+            eprintln!(
+                "\'{}\': disassembly range {:#X}:{:#X} is outside of the program ({:#X}:{:#X})",
+                program.display(),
+                range.start,
+                range.end,
+                cli.start,
+                end
+            );
+            return;
+        }
+    }
+
+    if let Some(offset) = cli.disasm_offset {
+        if offset >= rom.len() {
+            eprintln!(
+                "\'{}\': disassembly offset {} is outside of the program ({} bytes)",
+                program.display(),
+                offset,
+                rom.len()
+            );
+            return;
+        }
+    }
+
+    if cli.disasm
+        || cli.disasm_smart
+        || cli.disasm_out.is_some()
+        || cli.disasm_range.is_some()
+        || cli.disasm_offset.is_some()
+    {
+        if let Err(err) = write_disassembly(
+            &rom,
+            cli.start,
+            cli.disasm_offset.unwrap_or(0),
+            cli.disasm_smart,
+            cli.disasm_range.clone(),
+            cli.disasm_out.as_deref(),
+        ) {
+            let path = cli.disasm_out.unwrap_or_else(|| PathBuf::from("stdout"));
+            eprintln!("\'{}\': file could not be written: {}", path.display(), err);
+            return;
+        }
+    }
+
+    if cli.hexdump {
+        let mut w = stdout().lock();
+        if let Err(err) = hexdump_to(&rom, cli.start, &mut w).and_then(|_| w.flush()) {
+            eprintln!("\'stdout\': file could not be written: {}", err);
+            return;
+        }
+    }
+
+    if cli.check {
+        if !check(&program, &rom, cli.start) {
+            std::process::exit(1);
+        }
         return;
     }
 
-    if cli.disasm {
-        disassemble(&rom);
+    let quirks_given = cli.profile.is_some()
+        || cli.quirk_vf_reset.is_some()
+        || cli.quirk_memory.is_some()
+        || cli.quirk_wrap.is_some()
+        || cli.quirk_shift.is_some()
+        || cli.quirk_jump.is_some()
+        || cli.quirk_key_release.is_some()
+        || cli.quirk_collision_rows.is_some()
+        || cli.quirk_addi_overflow.is_some()
+        || cli.quirk_clip_origin.is_some();
+    let extra_entries = match &cli.quirks_db {
+        Some(path) => match quirks_db::load(path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("\'{}\': not a valid quirks database: {}", path.display(), e);
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+    if !cli.no_auto_quirks && !quirks_given {
+        if let Some(entry) = quirks_db::lookup(&rom, extra_entries) {
+            info!(
+                "\'{}\': using the {} profile for {}",
+                program.display(),
+                entry.profile,
+                entry.title
+            );
+            cli.profile = Some(entry.profile);
+            if let (Some(ipf), false) = (entry.ipf, ipf_given) {
+                cli.ipf = ipf;
+            }
+        }
     }
 
+    let keymap = match check_hotkeys(&cli.key_bindings)
+        .and_then(|()| build_keymap(&cli.key_bindings, |scancode| scancode.name().into()))
+    {
+        Ok(keymap) => keymap,
+        Err(e) => Cli::command().error(ErrorKind::ValueValidation, e).exit(),
+    };
+    let gamepad_keymap = match build_keymap(&cli.gamepad_bindings, Button::string) {
+        Ok(keymap) => keymap,
+        Err(e) => Cli::command().error(ErrorKind::ValueValidation, e).exit(),
+    };
+
     // Clap has already checked that `parse_color` will not return `Err` for these values;
     // there is no possibility of panicking.
-    let fg = parse_color(&cli.color).expect("Verified by clap");
-    let bg = parse_color(&cli.background).expect("Verified by clap");
+    let mut fg = parse_color(&cli.color).expect("Verified by clap");
+    let mut bg = parse_color(&cli.background).expect("Verified by clap");
+    if let Some(name) = &cli.theme {
+        let (theme_fg, theme_bg) = themes::theme(name).expect("Verified by clap");
+        if !color_given {
+            fg = theme_fg;
+        }
+        if !background_given {
+            bg = theme_bg;
+        }
+    }
 
+    let quirks = build_quirks(&cli);
     let options = Options {
+        title: program_title(&program),
+        playlist,
+        rom_duration: cli.rom_duration.map(Duration::from_secs_f64),
+        start: cli.start,
+        font,
+        stack_size: cli.stack_size,
+        patches: cli.patches,
+        backend: cli.backend,
         fps: cli.fps,
         ipf: cli.ipf,
+        timing: cli.timing,
         scale: cli.scale,
         fg,
         bg,
         pitch: cli.pitch,
-        display_wait: cli.display_wait,
-    };
-    let quirks = Quirks {
-        vf_reset: cli.quirk_vf_reset,
-        memory: cli.quirk_memory,
-        wrap: cli.quirk_wrap,
-        shifting: cli.quirk_shift,
-        jumping: cli.quirk_jump,
+        waveform: cli.waveform,
+        volume: cli.volume,
+        no_audio: cli.no_audio,
+        // The original COSMAC VIP interpreter waits for the display interrupt before drawing.
+        // The stricter --vblank-wait takes the place of --display-wait.
+        display_wait: !cli.vblank_wait
+            && (cli.display_wait || cli.profile.as_deref() == Some("cosmac")),
+        vblank_wait: cli.vblank_wait,
+        record: cli.record,
+        record_input: cli.record_input,
+        play_input: cli.play_input,
+        rewind_frames: cli
+            .rewind_seconds
+            .map_or(0, |secs| (secs * f64::from(cli.fps)).ceil() as usize),
+        seed: cli.seed,
+        #[cfg(feature = "overlay")]
+        overlay_font: cli.overlay_font,
+        keymap,
+        gamepad_keymap,
+        mouse_key: cli.mouse,
+        dump_state: cli.dump_state_on_exit,
+        screenshot_on_exit: cli.screenshot_on_exit,
+        json: cli.json,
+        trace: cli.trace,
+        trace_to: cli.trace_to,
+        step_log: cli.step_log,
+        trace_limit: cli.trace_limit,
+        profile_instructions: cli.profile_instructions,
+        log_draws: cli.log_draws,
+        frame_hash_every: cli.frame_hash_every,
+        halt_on_spin: cli.halt_on_spin,
+        trap_sys: cli.trap_sys,
+        watchpoints: cli.watchpoints,
+        warn_self_modify: cli.warn_self_modify,
+        warn_unknown: cli.warn_unknown,
+        no_clear: cli.no_clear,
+        ghosting: cli.ghosting,
+        crt_gradient: cli
+            .crt_gradient
+            .map(|color| parse_color(&color).expect("Verified by clap")),
+        vsync: cli.vsync,
+        adaptive_speed: cli.adaptive_speed,
+        pause_on_blur: cli.pause_on_blur,
+        integer_scale: cli.integer_scale,
+        quirks_b: cli.quirks_b,
     };
     let mut emu = match Emulator::new(&rom, options, quirks) {
         Ok(emu) => emu,
         Err(e) => {
             eprintln!(
                 "\'{}\': not a valid CHIP-8 program: {}",
-                cli.program.display(),
+                program.display(),
                 e
             );
             return;
         }
     };
+    if let Some(cycles) = cli.cycles {
+        match emu.run_headless(cycles) {
+            Ok(elapsed) if cli.bench => print_benchmark(cycles, elapsed, cli.json),
+            Ok(_) => {}
+            Err(e) => eprintln!("an unexpected error occurred: {}", e),
+        }
+        return;
+    }
     if let Err(e) = emu.run() {
         eprintln!("an unexpected error occurred: {}", e);
     }
 }
 
-fn disassemble(rom: &[u8]) {
-    let rom: Vec<u16> = rom
-        .chunks(2)
-        .map(|x| {
-            if x.len() == 2 {
-                u16::from_be_bytes([x[0], x[1]])
-            } else {
-                u16::from_be_bytes([x[0], 0])
-            }
-        })
-        .collect();
-    let mut addr = PROGRAM_START;
-    for instr in rom {
-        println!("{:#06X}: {}", addr, Instruction::from(instr));
-        addr += 2;
+/// Print the speed of executing `cycles` instructions in `elapsed` time
+fn print_benchmark(cycles: u64, elapsed: Duration, json: bool) {
+    let seconds = elapsed.as_secs_f64();
+    let ips = cycles as f64 / seconds;
+    let ns_per_step = elapsed.as_nanos() as f64 / cycles as f64;
+    if json {
+        let results = serde_json::json!({
+            "cycles": cycles,
+            "seconds": seconds,
+            "instructions_per_second": ips,
+            "ns_per_instruction": ns_per_step,
+        });
+        println!("{}", results);
+    } else {
+        println!("{} instructions in {:.3} s", cycles, seconds);
+        println!("{:.0} instructions per second", ips);
+        println!("{:.2} ns per instruction", ns_per_step);
+    }
+}
+
+/// Report every invalid instruction that is reachable from the start of `rom`, followed by
+/// the number of reachable instructions of each kind.  Returns whether all of them are valid.
+fn check(path: &Path, rom: &[u8], start: usize) -> bool {
+    let instructions = reachable_instructions(rom, start);
+    let mut counts: HashMap<&str, u64> = HashMap::new();
+    let mut invalid = 0;
+    for (addr, instr) in &instructions {
+        if let Instruction::Err(opcode) = instr {
+            eprintln!(
+                "\'{}\': {:#06X}: invalid instruction {:#06X}",
+                path.display(),
+                addr,
+                opcode
+            );
+            invalid += 1;
+        }
+        *counts.entry(instr.mnemonic()).or_default() += 1;
+    }
+
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a, x), (b, y)| y.cmp(x).then(a.cmp(b)));
+    println!("{:<5} {:>12}", "INSTR", "COUNT");
+    for (mnemonic, count) in counts {
+        println!("{:<5} {:>12}", mnemonic, count);
+    }
+    println!("{:<5} {:>12}", "TOTAL", instructions.len());
+    println!(
+        "{} reachable instructions, {} invalid",
+        instructions.len(),
+        invalid
+    );
+    invalid == 0
+}
+
+/// Print each glyph of the built-in font, 8 pixels wide, with `#` for set pixels and `.`
+/// for unset pixels
+fn print_font() {
+    for (digit, glyph) in FONT_DATA.chunks_exact(GLYPH_SIZE).enumerate() {
+        println!("{:X}:", digit);
+        for row in glyph {
+            let pixels: String = (0..8)
+                .map(|bit| if row & (0x80 >> bit) != 0 { '#' } else { '.' })
+                .collect();
+            println!("  {}  {:#04X}", pixels, row);
+        }
     }
 }
 
+/// Read the binary program at `path` with `load_program`, or print why it can't be run
+fn read_rom(path: &Path) -> Option<Vec<u8>> {
+    match load_program(path) {
+        Ok(rom) if rom.is_empty() => {
+            eprintln!(
+                "\'{}\': not a valid CHIP-8 program: file is empty",
+                path.display()
+            );
+            None
+        }
+        Ok(rom) => Some(rom),
+        Err(err) => {
+            eprintln!("\'{}\': file could not be opened: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Name of the program at `path`, which the window is named after
+fn program_title(path: &Path) -> String {
+    match path.file_stem() {
+        Some(stem) if path != Path::new("-") => stem.to_string_lossy().into_owned(),
+        _ => String::from("CHIP-8 Emulator"),
+    }
+}
+
+/// Read the binary program at `path`, from stdin if `path` is `-`, or from the web if
+/// `path` is an http:// or https:// URL
+fn load_program(path: &Path) -> io::Result<Vec<u8>> {
+    if path == Path::new("-") {
+        let mut rom = Vec::new();
+        stdin().read_to_end(&mut rom)?;
+        return Ok(rom);
+    }
+    #[cfg(feature = "fetch")]
+    if let Some(url) = path
+        .to_str()
+        .filter(|s| s.starts_with("http://") || s.starts_with("https://"))
+    {
+        let response = ureq::get(url).call().map_err(io::Error::other)?;
+        // Anything larger than memory is rejected later, so there's no need to download it
+        let mut rom = Vec::new();
+        response
+            .into_reader()
+            .take(chip8::MEMORY_SIZE as u64)
+            .read_to_end(&mut rom)?;
+        return Ok(rom);
+    }
+    read(path)
+}
+
+fn assemble(source: &Path, output: &Path) {
+    let code = match read_to_string(source) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!(
+                "\'{}\': file could not be opened: {}",
+                source.display(),
+                err
+            );
+            return;
+        }
+    };
+    let rom = match assembler::assemble(&code) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("\'{}\': {}", source.display(), err);
+            return;
+        }
+    };
+    if let Err(err) = write(output, rom) {
+        eprintln!(
+            "\'{}\': file could not be written: {}",
+            output.display(),
+            err
+        );
+    }
+}
+
+/// Quirks of the chosen profile, or of none, with each quirk given on the command line or in
+/// the configuration file turned on or off
+fn build_quirks(cli: &Cli) -> Quirks {
+    // Clap has already checked that the profile is one of the presets
+    let preset = match &cli.profile {
+        Some(profile) => Quirks::preset(profile).expect("Verified by clap"),
+        None => Quirks::default(),
+    };
+    Quirks {
+        vf_reset: cli.quirk_vf_reset.unwrap_or(preset.vf_reset),
+        memory: cli.quirk_memory.unwrap_or(preset.memory),
+        wrap: cli.quirk_wrap.unwrap_or(preset.wrap),
+        shifting: cli.quirk_shift.unwrap_or(preset.shifting),
+        jumping: cli.quirk_jump.unwrap_or(preset.jumping),
+        key_wait_release: cli.quirk_key_release.unwrap_or(preset.key_wait_release),
+        collision_rows: cli.quirk_collision_rows.unwrap_or(preset.collision_rows),
+        addi_overflow: cli.quirk_addi_overflow.unwrap_or(preset.addi_overflow),
+        clip_origin: cli.quirk_clip_origin.unwrap_or(preset.clip_origin),
+    }
+}
+
+/// Applies settings from a configuration file to every option that wasn't given on the
+/// command line.  Command line options take precedence over the configuration file, which
+/// takes precedence over the defaults.
+fn apply_config(cli: &mut Cli, matches: &ArgMatches, config: Config) -> Result<(), String> {
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let merge_flag = |flag: &mut bool, value: Option<bool>, id: &str| {
+        if let (Some(value), false) = (value, from_cli(id)) {
+            *flag = value;
+        }
+    };
+    let merge_quirk = |quirk: &mut Option<bool>, value: Option<bool>| {
+        if quirk.is_none() {
+            *quirk = value;
+        }
+    };
+
+    if let (Some(fps), false) = (config.fps, from_cli("fps")) {
+        validate_arg("fps", &fps.to_string())?;
+        cli.fps = fps;
+    }
+    if let (Some(ipf), false) = (config.ipf, from_cli("ipf")) {
+        validate_arg("ipf", &ipf.to_string())?;
+        cli.ipf = ipf;
+    }
+    if let (Some(scale), false) = (config.scale, from_cli("scale")) {
+        validate_arg("scale", &scale.to_string())?;
+        cli.scale = scale;
+    }
+    if let (Some(color), false) = (config.color, from_cli("color")) {
+        validate_arg("color", &color)?;
+        cli.color = color;
+    }
+    if let (Some(background), false) = (config.background, from_cli("background")) {
+        validate_arg("background", &background)?;
+        cli.background = background;
+    }
+    if let (Some(pitch), false) = (config.pitch, from_cli("pitch")) {
+        validate_arg("pitch", &pitch.to_string())?;
+        cli.pitch = pitch;
+    }
+    if let (Some(waveform), false) = (config.waveform, from_cli("waveform")) {
+        validate_arg("waveform", &waveform)?;
+        cli.waveform = Waveform::from_str(&waveform, false)?;
+    }
+    if let (Some(volume), false) = (config.volume, from_cli("volume")) {
+        validate_arg("volume", &volume.to_string())?;
+        cli.volume = volume;
+    }
+    if let (Some(theme), false) = (config.theme, from_cli("theme")) {
+        validate_arg("theme", &theme)?;
+        cli.theme = Some(theme);
+    }
+    if let (Some(profile), false) = (config.profile, from_cli("profile")) {
+        validate_arg("profile", &profile)?;
+        cli.profile = Some(profile);
+    }
+    merge_flag(&mut cli.display_wait, config.display_wait, "display_wait");
+    merge_flag(&mut cli.vblank_wait, config.vblank_wait, "vblank_wait");
+    merge_quirk(&mut cli.quirk_vf_reset, config.quirks.vf_reset);
+    merge_quirk(&mut cli.quirk_memory, config.quirks.memory);
+    merge_quirk(&mut cli.quirk_wrap, config.quirks.wrap);
+    merge_quirk(&mut cli.quirk_shift, config.quirks.shifting);
+    merge_quirk(&mut cli.quirk_jump, config.quirks.jumping);
+    merge_quirk(&mut cli.quirk_key_release, config.quirks.key_wait_release);
+    merge_quirk(&mut cli.quirk_collision_rows, config.quirks.collision_rows);
+    merge_quirk(&mut cli.quirk_addi_overflow, config.quirks.addi_overflow);
+    merge_quirk(&mut cli.quirk_clip_origin, config.quirks.clip_origin);
+
+    // Key bindings from the command line replace any binding of the same key or scancode
+    let mut key_bindings = Vec::new();
+    for (key, scancode) in config.keymap {
+        let (key, scancode) = parse_key_binding(&format!("{}={}", key, scancode))?;
+        if !cli
+            .key_bindings
+            .iter()
+            .any(|&(k, s)| k == key || s == scancode)
+        {
+            key_bindings.push((key, scancode));
+        }
+    }
+    key_bindings.append(&mut cli.key_bindings);
+    cli.key_bindings = key_bindings;
+
+    Ok(())
+}
+
+/// Checks `value` using the value parser of the option `id`, so that settings from a
+/// configuration file are validated the same way as command line options.
+fn validate_arg(id: &str, value: &str) -> Result<(), String> {
+    let arg = Cli::command()
+        .get_arguments()
+        .find(|arg| arg.get_id() == id)
+        .expect("Option exists")
+        .clone();
+    let long = format!("--{}", arg.get_long().expect("Option has a long name"));
+    Command::new("config")
+        .arg(arg)
+        .try_get_matches_from(["config", &long, value])
+        .map(|_| ())
+        .map_err(|_| format!("{} is not a valid value for {}", value, id))
+}
+
+/// Writes the disassembly of `rom` to `path`, or to stdout if `path` is `None`.
+fn write_disassembly(
+    rom: &[u8],
+    start: usize,
+    offset: usize,
+    smart: bool,
+    range: Option<Range<usize>>,
+    path: Option<&Path>,
+) -> io::Result<()> {
+    let mut w: Box<dyn Write> = match path {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(stdout().lock()),
+    };
+    if smart {
+        disassemble_smart_to(rom, start, range, &mut w)?;
+    } else {
+        disassemble_to(rom, start, offset, range, &mut w)?;
+    }
+    w.flush()
+}
+
 /// Verifies if the function `parse_color` will succeed.  This is used by
 /// `clap::value_parser`.
 fn verify_color(s: &str) -> Result<String, String> {
@@ -159,3 +1004,218 @@ fn parse_color(s: &str) -> Result<u32, String> {
             .map_err(|_| format!("{} is not a valid color in RGBA8888 format", s)),
     }
 }
+
+/// Parses an even hexadecimal address inside memory
+fn parse_start(s: &str) -> Result<usize, String> {
+    let addr = s.strip_prefix("0x").unwrap_or(s);
+    usize::from_str_radix(addr, 16)
+        .ok()
+        .filter(|&addr| addr < MEMORY_SIZE && addr.is_multiple_of(2))
+        .ok_or_else(|| format!("{} is not an even address inside memory", s))
+}
+
+/// Parses a range of even hexadecimal addresses inside memory, written as START:END
+fn parse_range(s: &str) -> Result<Range<usize>, String> {
+    let (start, end) = s
+        .split_once(':')
+        .ok_or_else(|| format!("{} is not a range of the form START:END", s))?;
+    let (start, end) = (parse_start(start)?, parse_start(end)?);
+    if start >= end {
+        return Err(format!("{} is an empty range", s));
+    }
+    Ok(start..end)
+}
+
+/// Parses a patch, written as a hexadecimal address inside memory, followed by `=` and
+/// the bytes to write in hexadecimal
+fn parse_patch(s: &str) -> Result<(usize, Vec<u8>), String> {
+    let (addr, hex) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not a patch of the form ADDR=BYTES", s))?;
+    let addr = usize::from_str_radix(addr.strip_prefix("0x").unwrap_or(addr), 16)
+        .ok()
+        .filter(|&addr| addr < MEMORY_SIZE)
+        .ok_or_else(|| format!("{} is not an address inside memory", addr))?;
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.is_empty() || !hex.len().is_multiple_of(2) {
+        return Err(format!(
+            "{} is not a whole number of hexadecimal bytes",
+            hex
+        ));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(|| format!("{} is not a valid hexadecimal byte string", hex))?;
+    if addr + bytes.len() > MEMORY_SIZE {
+        return Err(format!("{} does not fit in memory", s));
+    }
+    Ok((addr, bytes))
+}
+
+/// Parses a positive number of seconds
+fn parse_duration(s: &str) -> Result<f64, String> {
+    s.parse()
+        .ok()
+        .filter(|&secs: &f64| secs > 0.0 && secs.is_finite())
+        .ok_or_else(|| format!("{} is not a positive number of seconds", s))
+}
+
+/// Parses the name of a log level
+fn parse_log_level(s: &str) -> Result<LevelFilter, String> {
+    s.parse()
+        .map_err(|_| format!("{} is not a valid log level", s))
+}
+
+/// Parses a stack size of at least one address
+fn parse_stack_size(s: &str) -> Result<usize, String> {
+    s.parse()
+        .ok()
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("{} is not a positive number", s))
+}
+
+/// Parses a volume from 0.0 to 1.0
+fn parse_volume(s: &str) -> Result<f32, String> {
+    s.parse()
+        .ok()
+        .filter(|volume| (0.0..=1.0).contains(volume))
+        .ok_or_else(|| format!("{} is not a volume from 0.0 to 1.0", s))
+}
+
+/// Parses a key binding of the form `KEY=SCANCODE`, where `KEY` is a hexadecimal CHIP-8
+/// key and `SCANCODE` is the name of an SDL scancode.
+fn parse_key_binding(s: &str) -> Result<(u8, Scancode), String> {
+    let (key, name) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not of the form KEY=SCANCODE", s))?;
+    let scancode =
+        Scancode::from_name(name).ok_or_else(|| format!("{} is not a valid scancode", name))?;
+    Ok((parse_key(key)?, scancode))
+}
+
+/// Parses a gamepad binding of the form `KEY=BUTTON`, where `KEY` is a hexadecimal CHIP-8
+/// key and `BUTTON` is the name of an SDL game controller button.
+fn parse_gamepad_binding(s: &str) -> Result<(u8, Button), String> {
+    let (key, name) = s
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not of the form KEY=BUTTON", s))?;
+    let button =
+        Button::from_string(name).ok_or_else(|| format!("{} is not a valid button", name))?;
+    Ok((parse_key(key)?, button))
+}
+
+/// Parses a hexadecimal CHIP-8 key
+fn parse_key(key: &str) -> Result<u8, String> {
+    let key = key.strip_prefix("0x").unwrap_or(key);
+    u8::from_str_radix(key, 16)
+        .ok()
+        .filter(|&key| usize::from(key) < KEYPAD_SIZE)
+        .ok_or_else(|| format!("{} is not a valid CHIP-8 key", key))
+}
+
+/// Returns `Err` if a key binding uses a scancode that is reserved for a hotkey
+fn check_hotkeys(bindings: &[(u8, Scancode)]) -> Result<(), String> {
+    match bindings
+        .iter()
+        .find(|&&(_, scancode)| emulator::is_hotkey(scancode))
+    {
+        Some((_, scancode)) => Err(format!(
+            "{} is a hotkey and can't be mapped to a CHIP-8 key",
+            scancode.name()
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Collects key bindings into a map from inputs (scancodes or buttons) to CHIP-8 keys.
+/// Returns `Err` if a key or input is bound more than once.
+fn build_keymap<T>(
+    bindings: &[(u8, T)],
+    name: impl Fn(T) -> String,
+) -> Result<HashMap<T, u8>, String>
+where
+    T: Copy + Eq + Hash,
+{
+    let mut keymap = HashMap::new();
+    for &(key, input) in bindings {
+        if keymap.values().any(|&k| k == key) {
+            return Err(format!("key {:X} is mapped more than once", key));
+        }
+        if keymap.insert(input, key).is_some() {
+            return Err(format!("{} is mapped more than once", name(input)));
+        }
+    }
+    Ok(keymap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse `args` as the command line, then apply the configuration file `config`
+    fn cli_with_config(args: &[&str], config: &str) -> Cli {
+        let matches = Cli::command()
+            .try_get_matches_from(["ocho", "rom.ch8"].iter().chain(args))
+            .unwrap();
+        let mut cli = Cli::from_arg_matches(&matches).unwrap();
+        apply_config(&mut cli, &matches, toml::from_str(config).unwrap()).unwrap();
+        cli
+    }
+
+    #[test]
+    fn command_line_overrides_config_file() {
+        let config = "fps = 30\nipf = 20\ndisplay_wait = true\n[quirks]\nwrap = true\n";
+        let cli = cli_with_config(&["--ipf", "5"], config);
+        // From the command line
+        assert_eq!(cli.ipf, 5);
+        // From the configuration file
+        assert_eq!(cli.fps, 30);
+        assert!(cli.display_wait);
+        assert_eq!(cli.quirk_wrap, Some(true));
+        // Defaults
+        assert_eq!(cli.scale, 10);
+        assert!(!cli.vblank_wait);
+    }
+
+    #[test]
+    fn quirk_options_override_profile() {
+        let config = "profile = \"schip\"\n[quirks]\nshifting = true\nwrap = false\n";
+        let cli = cli_with_config(&["--quirk-jump=false", "--quirk-wrap"], config);
+        let quirks = build_quirks(&cli);
+        // Turned off, although the profile turns it on
+        assert!(!quirks.jumping);
+        // Turned on, although the profile doesn't
+        assert!(quirks.shifting);
+        // The command line takes precedence over the configuration file
+        assert!(quirks.wrap);
+        // From the profile
+        assert!(quirks.collision_rows);
+        assert!(!quirks.memory);
+    }
+
+    #[test]
+    fn hotkeys_cannot_be_mapped() {
+        let binding = |s| parse_key_binding(s).unwrap();
+        assert!(check_hotkeys(&[binding("5=Up"), binding("0=Space")]).is_ok());
+        assert!(check_hotkeys(&[binding("5=Up"), binding("A=M")]).is_err());
+        assert!(check_hotkeys(&[binding("1=F5")]).is_err());
+    }
+
+    #[test]
+    fn json_requires_output_to_format() {
+        let parse = |args: &[&str]| Cli::try_parse_from(["ocho", "rom.ch8"].iter().chain(args));
+        assert!(parse(&["--json"]).is_err());
+        assert!(parse(&["--json", "--dump-state-on-exit"]).is_ok());
+        assert!(parse(&["--json", "--cycles", "10", "--bench"]).is_ok());
+        assert!(parse(&[
+            "--json",
+            "--cycles",
+            "10",
+            "--bench",
+            "--dump-state-on-exit"
+        ])
+        .is_ok());
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{BlendMode, TextureCreator, WindowCanvas},
+    ttf::{Font, Sdl2TtfContext},
+    video::WindowContext,
+};
+
+use crate::{chip8::Chip8, instruction::Instruction};
+
+/// Point size of the overlay's text
+const FONT_SIZE: u16 = 14;
+/// Number of decoded instructions shown, starting at the program counter
+const INSTRUCTIONS: usize = 6;
+/// Distance (in window pixels) between the text and the edge of the panel
+const MARGIN: i32 = 4;
+/// Color of the overlay's text
+const TEXT_COLOR: Color = Color::RGB(0xFF, 0xFF, 0xFF);
+/// Color of the panel behind the text, translucent so the game stays visible
+const PANEL_COLOR: Color = Color::RGBA(0x00, 0x00, 0x00, 0xC0);
+
+/// Displays the registers, timers, and upcoming instructions over the game
+pub struct Overlay<'ttf> {
+    font: Font<'ttf, 'static>,
+}
+
+impl<'ttf> Overlay<'ttf> {
+    /// Load the TrueType font at `path` for rendering the overlay
+    pub fn load(ttf_context: &'ttf Sdl2TtfContext, path: &Path) -> Result<Self, String> {
+        let font = ttf_context.load_font(path, FONT_SIZE)?;
+        Ok(Self { font })
+    }
+
+    /// Draw the state of `chip` in the top-left corner of `canvas`.  The overlay is drawn
+    /// directly to the canvas, in window pixels, and never touches the display texture.
+    pub fn draw(
+        &self,
+        canvas: &mut WindowCanvas,
+        texture_creator: &TextureCreator<WindowContext>,
+        chip: &Chip8,
+    ) -> Result<(), String> {
+        let mut textures = Vec::new();
+        for line in lines(chip) {
+            let surface = self
+                .font
+                .render(&line)
+                .blended(TEXT_COLOR)
+                .map_err(|e| e.to_string())?;
+            let texture = texture_creator
+                .create_texture_from_surface(&surface)
+                .map_err(|e| e.to_string())?;
+            textures.push((texture, surface.width(), surface.height()));
+        }
+        let width = textures.iter().map(|&(_, w, _)| w).max().unwrap_or(0);
+        let line_height = self.font.recommended_line_spacing();
+
+        // Draw at the window's resolution, instead of the CHIP-8 display's
+        let (logical_width, logical_height) = canvas.logical_size();
+        let draw_color = canvas.draw_color();
+        canvas.set_logical_size(0, 0).map_err(|e| e.to_string())?;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(PANEL_COLOR);
+        canvas.fill_rect(Rect::new(
+            0,
+            0,
+            width + 2 * MARGIN as u32,
+            (line_height * textures.len() as i32 + 2 * MARGIN) as u32,
+        ))?;
+        for (row, (texture, w, h)) in textures.iter().enumerate() {
+            let y = MARGIN + line_height * row as i32;
+            canvas.copy(texture, None, Rect::new(MARGIN, y, *w, *h))?;
+        }
+
+        canvas.set_draw_color(draw_color);
+        canvas.set_blend_mode(BlendMode::None);
+        canvas
+            .set_logical_size(logical_width, logical_height)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Text of the overlay, one line at a time
+fn lines(chip: &Chip8) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "PC: {:#06X}  I: {:#06X}  SP: {:#04X}",
+            chip.pc(),
+            chip.i(),
+            chip.sp()
+        ),
+        format!("DT: {:#04X}  ST: {:#04X}", chip.dt, chip.st),
+    ];
+    for (x, row) in chip.v().chunks(4).enumerate() {
+        let registers: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!("V{:X}: {:#04X}", 4 * x + i, v))
+            .collect();
+        lines.push(registers.join("  "));
+    }
+
+    let mem = chip.mem();
+    for addr in (chip.pc()..).step_by(2).take(INSTRUCTIONS) {
+        match (mem.get(addr), mem.get(addr + 1)) {
+            (Some(&hi), Some(&lo)) => {
+                let instr = Instruction::from(u16::from_be_bytes([hi, lo]));
+                lines.push(format!("{:#06X}: {}", addr, instr));
+            }
+            _ => break,
+        }
+    }
+    lines
+}
@@ -0,0 +1,99 @@
+use std::{fs::read_to_string, path::Path};
+
+use ocho::chip8::Quirks;
+use serde::Deserialize;
+use sha1::{Digest, Sha1};
+
+/// Built-in database of programs, in JSON format
+const DATABASE: &str = include_str!("quirks_db.json");
+
+/// Recommended settings for a known program
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Entry {
+    /// SHA-1 hash of the program, in hexadecimal
+    pub sha1: String,
+    /// Title of the program
+    pub title: String,
+    /// Name of the quirk preset the program expects
+    pub profile: String,
+    /// Recommended instructions per frame
+    pub ipf: Option<u16>,
+}
+
+/// Load a database of programs in the same format as the built-in database
+pub fn load(path: &Path) -> Result<Vec<Entry>, String> {
+    let contents = read_to_string(path).map_err(|e| e.to_string())?;
+    let entries: Vec<Entry> = serde_json::from_str(&contents).map_err(|e| e.to_string())?;
+    for entry in &entries {
+        if Quirks::preset(&entry.profile).is_none() {
+            return Err(format!(
+                "unknown profile '{}' for {}",
+                entry.profile, entry.title
+            ));
+        }
+    }
+    Ok(entries)
+}
+
+/// Find the recommended settings for `rom` in `extra`, and then in the built-in database.
+/// Entries with an unknown profile are skipped entirely, including their instructions per
+/// frame.
+pub fn lookup(rom: &[u8], extra: Vec<Entry>) -> Option<Entry> {
+    let entries: Vec<Entry> =
+        serde_json::from_str(DATABASE).expect("Built-in database is valid JSON");
+    let hash = sha1_hex(rom);
+    extra
+        .into_iter()
+        .chain(entries)
+        .filter(|entry| Quirks::preset(&entry.profile).is_some())
+        .find(|entry| entry.sha1.eq_ignore_ascii_case(&hash))
+}
+
+/// SHA-1 hash of `rom`, in hexadecimal
+pub fn sha1_hex(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(rom: &[u8], profile: &str, ipf: u16) -> Entry {
+        Entry {
+            sha1: sha1_hex(rom),
+            title: "Test".into(),
+            profile: profile.into(),
+            ipf: Some(ipf),
+        }
+    }
+
+    #[test]
+    fn built_in_database_is_valid() {
+        let entries: Vec<Entry> = serde_json::from_str(DATABASE).unwrap();
+        for entry in entries {
+            assert_eq!(entry.sha1.len(), 40, "{}", entry.title);
+            assert!(entry.sha1.chars().all(|c| c.is_ascii_hexdigit()));
+            assert!(Quirks::preset(&entry.profile).is_some(), "{}", entry.title);
+        }
+    }
+
+    #[test]
+    fn finds_entries_by_hash() {
+        let rom = [0x12, 0x00];
+        let found = lookup(
+            &rom,
+            vec![entry(&[0x00, 0xE0], "schip", 30), entry(&rom, "cosmac", 15)],
+        );
+        assert!(found.is_some_and(|entry| entry.profile == "cosmac" && entry.ipf == Some(15)));
+    }
+
+    #[test]
+    fn skips_entries_with_unknown_profiles() {
+        let rom = [0x12, 0x00];
+        assert!(lookup(&rom, vec![entry(&rom, "chip48", 30)]).is_none());
+    }
+}
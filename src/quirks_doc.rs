@@ -0,0 +1,137 @@
+use crate::chip8::{Quirks, PRESETS};
+
+/// Documentation of a single quirk
+struct QuirkDoc {
+    /// Command line option that enables the quirk
+    option: &'static str,
+    /// Opcodes whose behavior changes
+    opcodes: &'static str,
+    /// What the quirk does
+    description: &'static str,
+    /// Whether the quirk is enabled in `Quirks`
+    enabled: fn(&Quirks) -> bool,
+    /// Enable the quirk in `Quirks`
+    enable: fn(&mut Quirks),
+}
+
+/// Every quirk, in the same order as the command line options
+const QUIRKS: [QuirkDoc; 9] = [
+    QuirkDoc {
+        option: "--quirk-vf-reset",
+        opcodes: "8xy1, 8xy2, 8xy3",
+        description: "AND, OR, and XOR reset the flags register (VF) to zero",
+        enabled: |quirks| quirks.vf_reset,
+        enable: |quirks| quirks.vf_reset = true,
+    },
+    QuirkDoc {
+        option: "--quirk-memory",
+        opcodes: "Fx55, Fx65",
+        description: "Save and load increment the index register past the last register",
+        enabled: |quirks| quirks.memory,
+        enable: |quirks| quirks.memory = true,
+    },
+    QuirkDoc {
+        option: "--quirk-wrap",
+        opcodes: "Dxyn",
+        description: "Sprites wrap around to the other side of the screen, instead of clipping",
+        enabled: |quirks| quirks.wrap,
+        enable: |quirks| quirks.wrap = true,
+    },
+    QuirkDoc {
+        option: "--quirk-shift",
+        opcodes: "8xy6, 8xyE",
+        description: "Shifts store the shifted value of Vy in Vx, instead of shifting Vx",
+        enabled: |quirks| quirks.shifting,
+        enable: |quirks| quirks.shifting = true,
+    },
+    QuirkDoc {
+        option: "--quirk-jump",
+        opcodes: "Bnnn",
+        description: "Jump with offset adds Vx, where x is the highest nibble of nnn, not V0",
+        enabled: |quirks| quirks.jumping,
+        enable: |quirks| quirks.jumping = true,
+    },
+    QuirkDoc {
+        option: "--quirk-key-release",
+        opcodes: "Fx0A",
+        description: "Wait for key completes when the key is released, instead of pressed",
+        enabled: |quirks| quirks.key_wait_release,
+        enable: |quirks| quirks.key_wait_release = true,
+    },
+    QuirkDoc {
+        option: "--quirk-collision-rows",
+        opcodes: "Dxyn",
+        description: "VF is set to the number of rows that collided or were clipped, not 0 or 1",
+        enabled: |quirks| quirks.collision_rows,
+        enable: |quirks| quirks.collision_rows = true,
+    },
+    QuirkDoc {
+        option: "--quirk-addi-overflow",
+        opcodes: "Fx1E",
+        description: "VF is set to 1 when the index register overflows past the end of memory",
+        enabled: |quirks| quirks.addi_overflow,
+        enable: |quirks| quirks.addi_overflow = true,
+    },
+    QuirkDoc {
+        option: "--quirk-clip-origin",
+        opcodes: "Dxyn",
+        description: "Sprites starting off the screen aren't drawn, instead of wrapping around",
+        enabled: |quirks| quirks.clip_origin,
+        enable: |quirks| quirks.clip_origin = true,
+    },
+];
+
+/// Print each quirk, the opcodes it affects, and the platforms that enable it
+pub fn print_quirks() {
+    for quirk in &QUIRKS {
+        let platforms: Vec<&str> = PRESETS
+            .into_iter()
+            .filter(|name| Quirks::preset(name).is_some_and(|preset| (quirk.enabled)(&preset)))
+            .collect();
+        let platforms = if platforms.is_empty() {
+            "none".to_string()
+        } else {
+            platforms.join(", ")
+        };
+        println!("{}", quirk.option);
+        println!("    {}", quirk.description);
+        println!("    Opcodes: {}", quirk.opcodes);
+        println!("    Platforms: {}", platforms);
+    }
+}
+
+/// Parse a comma-separated list of profiles and quirks, named like their options without
+/// the `--profile` and `--quirk-` prefixes (e.g., `cosmac` or `chip8,shift,jump`).  Every
+/// quirk of each profile, and every named quirk, is enabled.
+pub fn parse_quirks(s: &str) -> Result<Quirks, String> {
+    let mut quirks = Quirks::default();
+    for name in s.split(',').map(str::trim) {
+        if let Some(preset) = Quirks::preset(name) {
+            for quirk in QUIRKS.iter().filter(|quirk| (quirk.enabled)(&preset)) {
+                (quirk.enable)(&mut quirks);
+            }
+        } else if let Some(quirk) = QUIRKS
+            .iter()
+            .find(|quirk| quirk.option.strip_prefix("--quirk-") == Some(name))
+        {
+            (quirk.enable)(&mut quirks);
+        } else {
+            return Err(format!("{} is not a profile or quirk", name));
+        }
+    }
+    Ok(quirks)
+}
+
+/// Options of the quirks enabled in `quirks`, separated by spaces, or `none`
+pub fn describe(quirks: &Quirks) -> String {
+    let options: Vec<&str> = QUIRKS
+        .iter()
+        .filter(|quirk| (quirk.enabled)(quirks))
+        .map(|quirk| quirk.option)
+        .collect();
+    if options.is_empty() {
+        "none".to_string()
+    } else {
+        options.join(" ")
+    }
+}
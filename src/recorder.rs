@@ -0,0 +1,117 @@
+use std::{borrow::Cow, fs::File, io::BufWriter, path::Path};
+
+use gif::{Encoder, Frame, Repeat};
+
+use crate::{
+    chip8::{DISPLAY_HEIGHT, DISPLAY_WIDTH},
+    framebuffer::Framebuffer,
+    screenshot::composite_over_black,
+};
+
+/// Largest scale factor used for recorded frames; larger window scales are downsampled
+/// to keep file sizes reasonable
+const MAX_RECORDING_SCALE: usize = 4;
+/// Palette index of unset pixels
+const BG_INDEX: u8 = 0;
+/// Palette index of set pixels
+const FG_INDEX: u8 = 1;
+/// Shortest delay between frames, in hundredths of a second.  Many viewers play shorter
+/// delays as fast as possible, or slow them down to 1/10 of a second.
+const MIN_DELAY: u64 = 2;
+
+/// Records the display as an animated GIF
+pub struct Recorder {
+    encoder: Encoder<BufWriter<File>>,
+    /// Scale factor of recorded frames
+    scale: usize,
+    /// Target frames per second
+    fps: u16,
+    /// Number of frames recorded so far, including skipped frames
+    frames: u64,
+    /// Total delay of the frames written so far, in hundredths of a second
+    written: u64,
+    /// Frames are only recorded while active
+    pub active: bool,
+}
+
+impl Recorder {
+    /// Create a GIF at `path` using the RGBA8888 colors `fg` and `bg`.  Recording is
+    /// initially inactive.
+    pub fn create(path: &Path, scale: u32, fps: u16, fg: &[u8], bg: &[u8]) -> Result<Self, String> {
+        let scale = usize::try_from(scale)
+            .unwrap_or(MAX_RECORDING_SCALE)
+            .min(MAX_RECORDING_SCALE);
+        let mut fg = [fg[0], fg[1], fg[2], fg[3]];
+        let mut bg = [bg[0], bg[1], bg[2], bg[3]];
+        composite_over_black(&mut fg);
+        composite_over_black(&mut bg);
+        let palette = [bg[0], bg[1], bg[2], fg[0], fg[1], fg[2]];
+
+        let file = File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = Encoder::new(
+            BufWriter::new(file),
+            (DISPLAY_WIDTH * scale) as u16,
+            (DISPLAY_HEIGHT * scale) as u16,
+            &palette,
+        )
+        .map_err(|e| e.to_string())?;
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            encoder,
+            scale,
+            fps,
+            frames: 0,
+            written: 0,
+            active: false,
+        })
+    }
+
+    /// Append the current contents of `fb` as a new frame, if recording is active
+    pub fn record(&mut self, fb: &Framebuffer) -> Result<(), String> {
+        if !self.active {
+            return Ok(());
+        }
+
+        // GIF delays are in hundredths of a second, so track the total elapsed time to
+        // avoid accumulating rounding errors.  Above 50 frames per second, frames that would
+        // be shown too briefly are skipped, and their time is added to the next frame.
+        self.frames += 1;
+        let elapsed = self.frames * 100 / u64::from(self.fps);
+        let delay = elapsed - self.written;
+        if delay < MIN_DELAY {
+            return Ok(());
+        }
+        self.written = elapsed;
+
+        let width = DISPLAY_WIDTH * self.scale;
+        let height = DISPLAY_HEIGHT * self.scale;
+        let mut buffer = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                if fb[(x / self.scale, y / self.scale)] {
+                    buffer.push(FG_INDEX);
+                } else {
+                    buffer.push(BG_INDEX);
+                }
+            }
+        }
+
+        let frame = Frame {
+            width: width as u16,
+            height: height as u16,
+            delay: delay as u16,
+            buffer: Cow::Owned(buffer),
+            ..Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(|e| e.to_string())
+    }
+
+    /// Write the end of the GIF and flush it to disk
+    pub fn finish(self) -> Result<(), String> {
+        self.encoder.into_inner().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,44 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::{imageops, imageops::FilterType, RgbaImage};
+
+/// Save RGBA8888 `pixels` of a display that is `width` by `height` pixels as a PNG
+/// image, scaled by `scale`.  Pixels are composited over black, which is how they
+/// appear in the emulator window.
+pub fn save_png(
+    path: &Path,
+    mut pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+    scale: u32,
+) -> Result<(), String> {
+    for pixel in pixels.chunks_exact_mut(4) {
+        composite_over_black(pixel);
+    }
+
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .ok_or("pixel data does not match the display size")?;
+    let image = imageops::resize(&image, width * scale, height * scale, FilterType::Nearest);
+    image.save(path).map_err(|e| e.to_string())
+}
+
+/// Composite an RGBA8888 pixel over black, making it fully opaque
+pub fn composite_over_black(pixel: &mut [u8]) {
+    let alpha = u16::from(pixel[3]);
+    for channel in &mut pixel[0..3] {
+        *channel = (u16::from(*channel) * alpha / 255) as u8;
+    }
+    pixel[3] = 0xFF;
+}
+
+/// Path of a new screenshot in the current directory, named after the current time
+pub fn timestamped_path() -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    PathBuf::from(format!("ocho-{}.png", millis))
+}
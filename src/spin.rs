@@ -0,0 +1,95 @@
+use std::ops::RangeInclusive;
+
+/// Number of consecutive frames a program must spend looping over the same few instructions
+/// before it is reported as halted
+const SPIN_FRAMES: u32 = 30;
+/// Largest distance, in bytes, between the instructions of a loop that counts as halted.  Two
+/// instructions are enough for a loop that waits for a key (e.g., SKP followed by JMP).
+const SPIN_WINDOW: usize = 2;
+
+/// Detects programs that have halted by looping over one or two instructions, frame after
+/// frame
+#[derive(Debug, Default)]
+pub struct SpinDetector {
+    /// Addresses of the instructions executed so far this frame, from lowest to highest
+    frame: Option<RangeInclusive<usize>>,
+    /// Addresses of the instructions executed during the previous frame
+    previous: Option<RangeInclusive<usize>>,
+    /// Number of consecutive frames spent looping over the same instructions
+    frames: u32,
+}
+
+impl SpinDetector {
+    /// Record that the instruction at `pc` was executed
+    pub fn executed(&mut self, pc: usize) {
+        self.frame = Some(match self.frame.take() {
+            Some(range) => *range.start().min(&pc)..=*range.end().max(&pc),
+            None => pc..=pc,
+        });
+    }
+
+    /// End the frame.  Returns the address of the loop once the program has spent
+    /// `SPIN_FRAMES` frames in a row looping over the same instructions, and `None` otherwise.
+    /// Frames in which nothing was executed (e.g., while paused) are ignored.
+    pub fn end_frame(&mut self) -> Option<usize> {
+        let frame = self.frame.take()?;
+        let tight = frame.end() - frame.start() <= SPIN_WINDOW;
+        if tight && self.previous.as_ref() == Some(&frame) {
+            self.frames = self.frames.saturating_add(1);
+        } else {
+            self.frames = u32::from(tight);
+        }
+        let start = *frame.start();
+        self.previous = Some(frame);
+        (self.frames == SPIN_FRAMES).then_some(start)
+    }
+
+    /// Forget the instructions executed so far, e.g., after switching programs
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run `frames` frames that each execute the instructions at `pcs`.  Returns the frames
+    /// in which the program was reported as halted.
+    fn run(detector: &mut SpinDetector, frames: u32, pcs: &[usize]) -> Vec<u32> {
+        (1..=frames)
+            .filter(|_| {
+                for &pc in pcs {
+                    detector.executed(pc);
+                }
+                detector.end_frame().is_some()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_jump_to_itself() {
+        let mut detector = SpinDetector::default();
+        assert_eq!(run(&mut detector, 100, &[0x230]), vec![SPIN_FRAMES]);
+    }
+
+    #[test]
+    fn detects_two_instruction_loop() {
+        let mut detector = SpinDetector::default();
+        assert_eq!(run(&mut detector, 100, &[0x230, 0x232]), vec![SPIN_FRAMES]);
+    }
+
+    #[test]
+    fn ignores_longer_loops() {
+        let mut detector = SpinDetector::default();
+        assert!(run(&mut detector, 100, &[0x230, 0x232, 0x234]).is_empty());
+    }
+
+    #[test]
+    fn ignores_loops_that_move() {
+        let mut detector = SpinDetector::default();
+        for frame in 0..100 {
+            assert!(run(&mut detector, 1, &[0x200 + 2 * frame]).is_empty());
+        }
+    }
+}
@@ -0,0 +1,56 @@
+/// A named pair of foreground and background colors
+pub struct Theme {
+    pub name: &'static str,
+    /// Foreground color (RGBA8888)
+    pub fg: u32,
+    /// Background color (RGBA8888)
+    pub bg: u32,
+}
+
+/// Every theme, selected with `--theme`
+pub const THEMES: [Theme; 5] = [
+    Theme {
+        name: "bw",
+        fg: 0xFFFFFFFF,
+        bg: 0x000000FF,
+    },
+    Theme {
+        name: "inverse",
+        fg: 0x000000FF,
+        bg: 0xFFFFFFFF,
+    },
+    Theme {
+        name: "green-crt",
+        fg: 0x33FF66FF,
+        bg: 0x0A1A0FFF,
+    },
+    Theme {
+        name: "amber",
+        fg: 0xFFB000FF,
+        bg: 0x1A1000FF,
+    },
+    Theme {
+        name: "gameboy",
+        fg: 0x0F380FFF,
+        bg: 0x9BBC0FFF,
+    },
+];
+
+/// Foreground and background colors of the theme called `name`, or `None` if there is
+/// no such theme
+pub fn theme(name: &str) -> Option<(u32, u32)> {
+    THEMES
+        .iter()
+        .find(|theme| theme.name == name)
+        .map(|theme| (theme.fg, theme.bg))
+}
+
+/// Print each theme and its colors
+pub fn print_themes() {
+    for theme in &THEMES {
+        println!(
+            "{:<10} foreground {:#010X}, background {:#010X}",
+            theme.name, theme.fg, theme.bg
+        );
+    }
+}
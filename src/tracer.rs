@@ -0,0 +1,90 @@
+use std::{
+    fs::File,
+    io::{stderr, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{
+    chip8::{Chip8, NUMBER_OF_REGISTERS},
+    instruction::Instruction,
+};
+
+/// Logs executed instructions, along with the registers they changed
+pub struct Tracer {
+    out: Box<dyn Write>,
+    /// Largest number of lines to log
+    limit: Option<u64>,
+    /// Number of lines logged so far
+    lines: u64,
+}
+
+/// Registers that may be changed by an instruction
+pub struct Registers {
+    v: [u8; NUMBER_OF_REGISTERS],
+    i: usize,
+}
+
+impl Registers {
+    /// Current registers of `chip`
+    pub fn of(chip: &Chip8) -> Self {
+        Self {
+            v: *chip.v(),
+            i: chip.i(),
+        }
+    }
+}
+
+impl Tracer {
+    /// Log to the file at `path`, or to stderr if there is no path.  At most `limit` lines
+    /// are logged, if given.
+    pub fn create(path: Option<&Path>, limit: Option<u64>) -> Result<Self, String> {
+        let out: Box<dyn Write> = match path {
+            Some(path) => Box::new(BufWriter::new(
+                File::create(path).map_err(|e| e.to_string())?,
+            )),
+            None => Box::new(stderr()),
+        };
+        Ok(Self {
+            out,
+            limit,
+            lines: 0,
+        })
+    }
+
+    /// Log `instr`, executed at address `pc`, comparing the registers of `chip` with
+    /// `before` they were executed
+    pub fn trace(
+        &mut self,
+        pc: usize,
+        instr: &Instruction,
+        before: &Registers,
+        chip: &Chip8,
+    ) -> Result<(), String> {
+        if self.limit.is_some_and(|limit| self.lines >= limit) {
+            return Ok(());
+        }
+        self.lines += 1;
+
+        let mut changes = Vec::new();
+        for (x, (old, new)) in before.v.iter().zip(chip.v()).enumerate() {
+            if old != new {
+                changes.push(format!("V{:X}={:#04X}", x, new));
+            }
+        }
+        if before.i != chip.i() {
+            changes.push(format!("I={:#06X}", chip.i()));
+        }
+        let line = format!(
+            "{:#06X}: {:<16} {}",
+            pc,
+            instr.to_string(),
+            changes.join(" ")
+        );
+        writeln!(self.out, "{}", line.trim_end()).map_err(|e| e.to_string())
+    }
+
+    /// Flush any buffered lines
+    pub fn finish(mut self) -> Result<(), String> {
+        self.out.flush().map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,46 @@
+use ocho::chip8::{Chip8, MEMORY_SIZE, NUMBER_OF_REGISTERS};
+
+/// A location that pauses the emulator when its value changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watchpoint {
+    /// A general purpose register, V0 through VF
+    Register(usize),
+    /// A byte of memory
+    Memory(usize),
+}
+
+impl Watchpoint {
+    /// Parse a register name (e.g., `V3`), or a memory address in hexadecimal (e.g., `0x2F0`)
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if let Some(x) = s.strip_prefix('V').or_else(|| s.strip_prefix('v')) {
+            return usize::from_str_radix(x, 16)
+                .ok()
+                .filter(|&x| x < NUMBER_OF_REGISTERS)
+                .map(Watchpoint::Register)
+                .ok_or_else(|| format!("{} is not a valid register", s));
+        }
+        let addr = s.strip_prefix("0x").unwrap_or(s);
+        usize::from_str_radix(addr, 16)
+            .ok()
+            .filter(|&addr| addr < MEMORY_SIZE)
+            .map(Watchpoint::Memory)
+            .ok_or_else(|| format!("{} is not a valid register or memory address", s))
+    }
+
+    /// Current value of the location in `chip`
+    pub fn value(&self, chip: &Chip8) -> u8 {
+        match *self {
+            Watchpoint::Register(x) => chip.v()[x],
+            Watchpoint::Memory(addr) => chip.mem()[addr],
+        }
+    }
+}
+
+impl std::fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Watchpoint::Register(x) => write!(f, "V{:X}", x),
+            Watchpoint::Memory(addr) => write!(f, "{:#05X}", addr),
+        }
+    }
+}